@@ -1,4 +1,6 @@
-use fmc::prelude::*;
+use std::sync::Once;
+
+use fmc::{blocks::Blocks, database::DatabasePlugin, networking::ServerPlugin, prelude::*};
 
 pub struct ExtractBundledAssetsPlugin;
 impl Plugin for ExtractBundledAssetsPlugin {
@@ -28,3 +30,34 @@ impl Plugin for ExtractBundledAssetsPlugin {
         }
     }
 }
+
+/// Load the blocks the same way the server does, but without starting it. Tools and tests that
+/// only need the blocks use this. The block ids are assigned in a temporary database, so the
+/// world is never touched, and the ids might not be the same as the world's.
+pub fn load_blocks() -> &'static Blocks {
+    static LOAD_BLOCKS: Once = Once::new();
+    LOAD_BLOCKS.call_once(|| {
+        let database_path =
+            std::env::temp_dir().join(format!("fmc_blocks_{}.sqlite", std::process::id()));
+
+        let mut app = App::new();
+        app.add_plugins(ExtractBundledAssetsPlugin).add_plugins(
+            fmc::DefaultPlugins
+                .build()
+                .set(DatabasePlugin {
+                    path: database_path.to_string_lossy().into_owned(),
+                })
+                .disable::<ServerPlugin>(),
+        );
+        app.finish();
+        app.cleanup();
+
+        // The blocks are loaded at startup, nothing after it needs to run.
+        app.world_mut().run_schedule(PreStartup);
+        app.world_mut().run_schedule(Startup);
+        app.world_mut().run_schedule(PostStartup);
+
+        std::fs::remove_file(&database_path).ok();
+    });
+    Blocks::get()
+}
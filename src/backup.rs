@@ -0,0 +1,273 @@
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use fmc::{
+    bevy::{
+        log::{error, info},
+        tasks::{IoTaskPool, Task},
+    },
+    database::Database,
+    prelude::*,
+};
+use futures_lite::future;
+use rusqlite::DatabaseName;
+
+use crate::{console::ConsoleCommand, settings::Settings};
+
+/// Takes backups of the world database while the server is running. Backups are taken
+/// periodically and with the 'backup' console command. Only the newest 'backup-count' backups are
+/// kept.
+pub struct BackupPlugin;
+impl Plugin for BackupPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup)
+            .add_systems(Update, (start_backup, finish_backup).chain());
+    }
+}
+
+#[derive(Resource)]
+struct Backups {
+    directory: PathBuf,
+    world_name: String,
+    keep: usize,
+    // None if periodic backups are disabled
+    timer: Option<Timer>,
+    // Backup that is currently being written
+    task: Option<Task<Result<PathBuf, String>>>,
+}
+
+fn setup(mut commands: Commands, settings: Res<Settings>) {
+    let timer = if settings.backup_interval == 0 {
+        None
+    } else {
+        Some(Timer::new(
+            std::time::Duration::from_secs(settings.backup_interval as u64 * 60),
+            TimerMode::Repeating,
+        ))
+    };
+
+    commands.insert_resource(Backups {
+        directory: PathBuf::from(&settings.backup_directory),
+        world_name: settings.world_name().to_owned(),
+        keep: settings.backup_count,
+        timer,
+        task: None,
+    });
+}
+
+fn start_backup(
+    time: Res<Time>,
+    database: Res<Database>,
+    mut backups: ResMut<Backups>,
+    mut console_commands: EventReader<ConsoleCommand>,
+) {
+    let mut requested = false;
+    for command in console_commands.read() {
+        if command.name == "backup" {
+            requested = true;
+        }
+    }
+
+    if let Some(timer) = backups.timer.as_mut() {
+        timer.tick(time.delta());
+        requested |= timer.just_finished();
+    }
+
+    if !requested {
+        return;
+    }
+
+    if backups.task.is_some() {
+        info!("A backup is already in progress");
+        return;
+    }
+
+    let database = database.clone();
+    let directory = backups.directory.clone();
+    let world_name = backups.world_name.clone();
+    let keep = backups.keep;
+
+    info!("Starting backup of '{}'", world_name);
+
+    backups.task = Some(IoTaskPool::get().spawn(async move {
+        let backup_path = create_backup(&database, &directory, &world_name)?;
+        remove_old_backups(&directory, &world_name, keep);
+        Ok(backup_path)
+    }));
+}
+
+fn finish_backup(mut backups: ResMut<Backups>) {
+    let Some(task) = backups.task.as_mut() else {
+        return;
+    };
+
+    let Some(result) = future::block_on(future::poll_once(task)) else {
+        return;
+    };
+
+    match result {
+        Ok(path) => info!("Backup written to {}", path.display()),
+        Err(e) => error!("Failed to back up the world\nError: {}", e),
+    }
+
+    backups.task = None;
+}
+
+/// Copy the database through SQLite's online backup API and compress it into the backup
+/// directory. The server can keep writing to the database while the copy is made.
+fn create_backup(
+    database: &Database,
+    directory: &Path,
+    world_name: &str,
+) -> Result<PathBuf, String> {
+    std::fs::create_dir_all(directory).map_err(|e| {
+        format!(
+            "Could not create the backup directory at: {}\n{}",
+            directory.display(),
+            e
+        )
+    })?;
+
+    // In milliseconds, so backups taken within the same second don't overwrite each other.
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis();
+    let snapshot_path = directory.join(format!("{}-{}.sqlite", world_name, timestamp));
+    let archive_path = directory.join(format!("{}-{}.tar.zst", world_name, timestamp));
+
+    let conn = database.get_connection();
+    conn.backup(DatabaseName::Main, &snapshot_path, None)
+        .map_err(|e| e.to_string())?;
+
+    let result = compress(&snapshot_path, &archive_path, world_name).map_err(|e| e.to_string());
+    let _ = std::fs::remove_file(&snapshot_path);
+
+    result.map(|_| archive_path)
+}
+
+fn compress(snapshot_path: &Path, archive_path: &Path, world_name: &str) -> std::io::Result<()> {
+    let file = File::create(archive_path)?;
+    let encoder = zstd::stream::Encoder::new(file, 3)?;
+
+    let mut archive = tar::Builder::new(encoder);
+    archive.append_path_with_name(snapshot_path, format!("{}.sqlite", world_name))?;
+    archive.into_inner()?.finish()?;
+
+    Ok(())
+}
+
+/// All backups of the world in the directory, newest first.
+fn list_backups(directory: &Path, world_name: &str) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(directory) else {
+        return Vec::new();
+    };
+
+    let mut backups: Vec<(u128, PathBuf)> = entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let timestamp = path
+                .file_name()?
+                .to_str()?
+                .strip_prefix(world_name)?
+                .strip_prefix('-')?
+                .strip_suffix(".tar.zst")?
+                .parse()
+                .ok()?;
+            Some((timestamp, path))
+        })
+        .collect();
+
+    backups.sort_unstable_by(|a, b| b.0.cmp(&a.0));
+    backups.into_iter().map(|(_, path)| path).collect()
+}
+
+fn remove_old_backups(directory: &Path, world_name: &str, keep: usize) {
+    for path in list_backups(directory, world_name).into_iter().skip(keep) {
+        if let Err(e) = std::fs::remove_file(&path) {
+            error!(
+                "Failed to remove old backup at: {}\nError: {}",
+                path.display(),
+                e
+            );
+        }
+    }
+}
+
+/// Replace the world database with the contents of a backup. 'backup' is either the path of a
+/// backup archive or "latest" to use the newest backup in the backup directory.
+///
+/// This must be done before the server opens the database.
+pub fn restore(settings: &Settings, backup: &str) {
+    let archive_path = if backup == "latest" {
+        let directory = Path::new(&settings.backup_directory);
+        match list_backups(directory, settings.world_name())
+            .into_iter()
+            .next()
+        {
+            Some(path) => path,
+            None => panic!(
+                "Could not restore the world, there are no backups of '{}' in: {}",
+                settings.world_name(),
+                directory.display()
+            ),
+        }
+    } else {
+        PathBuf::from(backup)
+    };
+
+    let file = match File::open(&archive_path) {
+        Ok(f) => f,
+        Err(e) => panic!(
+            "Failed to open backup at path: {}\nError: {}",
+            archive_path.display(),
+            e
+        ),
+    };
+
+    let decoder = zstd::stream::Decoder::new(file).unwrap();
+    let mut archive = tar::Archive::new(decoder);
+
+    let snapshot_path = PathBuf::from(settings.database_path.clone() + ".restore");
+    let mut entries = archive.entries().unwrap();
+    match entries.next() {
+        Some(Ok(mut entry)) => {
+            if let Err(e) = entry.unpack(&snapshot_path) {
+                panic!(
+                    "Failed to extract backup at path: {}\nError: {}",
+                    archive_path.display(),
+                    e
+                );
+            }
+        }
+        _ => panic!(
+            "The backup at path: {} is empty or corrupt",
+            archive_path.display()
+        ),
+    }
+
+    let mut conn = rusqlite::Connection::open(&settings.database_path).unwrap();
+    let result = conn.restore(
+        DatabaseName::Main,
+        &snapshot_path,
+        None::<fn(rusqlite::backup::Progress)>,
+    );
+    let _ = std::fs::remove_file(&snapshot_path);
+
+    if let Err(e) = result {
+        panic!(
+            "Failed to restore the world from backup at: {}\nError: {}",
+            archive_path.display(),
+            e
+        );
+    }
+
+    println!(
+        "Restored '{}' from {}",
+        settings.database_path,
+        archive_path.display()
+    );
+}
@@ -0,0 +1,57 @@
+use std::sync::{mpsc, Mutex};
+
+use fmc::prelude::*;
+
+/// Reads admin commands from the server's standard input. Each line is turned into a
+/// [ConsoleCommand] event that the plugins owning the command can react to.
+pub struct ConsolePlugin;
+impl Plugin for ConsolePlugin {
+    fn build(&self, app: &mut App) {
+        let (sender, receiver) = mpsc::channel();
+
+        // Reading stdin blocks, so it is done on its own thread and passed over a channel.
+        std::thread::spawn(move || {
+            for line in std::io::stdin().lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        app.add_event::<ConsoleCommand>()
+            .insert_resource(ConsoleInput(Mutex::new(receiver)))
+            .add_systems(PreUpdate, read_console_input);
+    }
+}
+
+#[derive(Resource)]
+struct ConsoleInput(Mutex<mpsc::Receiver<String>>);
+
+/// A command entered in the server console, e.g. "backup" or "/time set 600". The leading slash
+/// is optional.
+#[derive(Event)]
+pub struct ConsoleCommand {
+    pub name: String,
+    pub args: Vec<String>,
+}
+
+fn read_console_input(
+    console_input: Res<ConsoleInput>,
+    mut console_commands: EventWriter<ConsoleCommand>,
+) {
+    let receiver = console_input.0.lock().unwrap();
+    for line in receiver.try_iter() {
+        let mut words = line.split_whitespace();
+        let Some(name) = words.next() else {
+            continue;
+        };
+
+        console_commands.send(ConsoleCommand {
+            name: name.trim_start_matches('/').to_owned(),
+            args: words.map(|word| word.to_owned()).collect(),
+        });
+    }
+}
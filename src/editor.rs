@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+
+use fmc::{
+    bevy::math::DVec3,
+    items::{Item, ItemId, ItemStack},
+};
+
+use crate::{players::PlayerSave, settings::Settings, world::WorldProperties};
+
+const USAGE: &str = "\
+Usage: server world <command>
+
+Commands:
+    players                                     List all players with a save
+    properties                                  Print the world properties
+    player <name>                               Print a player's save
+    player <name> set-position <x> <y> <z>      Move a player
+    player <name> set-health <hearts>           Set a player's health
+    player <name> set-slot <index> <item> <count>
+                                                Replace an inventory slot, use count 0 to empty it
+    player <name> give <item> [count]           Add items to the first free inventory slots
+    player <name> clear-inventory               Remove all items from the inventory
+    player <name> reset                         Delete the save, the player starts over on join";
+
+/// Inspect and edit the world database without starting the server. Must not be used while the
+/// server is running, the server will overwrite the changes with its own state.
+pub fn run(settings: &Settings, args: Vec<String>) {
+    if !std::path::Path::new(&settings.database_path).exists() {
+        eprintln!("There is no world at: {}", settings.database_path);
+        return;
+    }
+
+    let conn = rusqlite::Connection::open(&settings.database_path).unwrap();
+    let args: Vec<&str> = args.iter().map(|arg| arg.as_str()).collect();
+
+    match args.as_slice() {
+        ["players"] => {
+            for name in PlayerSave::list(&conn) {
+                println!("{}", name);
+            }
+        }
+        ["properties"] => {
+            let Some(properties) = WorldProperties::read(&conn) else {
+                println!("The world has no stored properties");
+                return;
+            };
+            println!("{}", serde_json::to_string_pretty(&properties).unwrap());
+        }
+        ["player", name, "reset"] => {
+            if PlayerSave::delete(name, &conn) {
+                println!("Reset '{}'", name);
+            } else {
+                eprintln!("There is no player by the name '{}'", name);
+            }
+        }
+        ["player", name, edit @ ..] => {
+            let Some(mut save) = PlayerSave::read(name, &conn) else {
+                eprintln!("There is no player by the name '{}'", name);
+                return;
+            };
+
+            let items = ItemNames::load(&conn);
+
+            if edit.is_empty() {
+                print_save(&save, &items);
+                return;
+            }
+
+            if let Err(e) = edit_save(&mut save, &items, edit) {
+                eprintln!("{}\n\n{}", e, USAGE);
+                return;
+            }
+
+            save.write(name, &conn);
+            print_save(&save, &items);
+        }
+        _ => eprintln!("{}", USAGE),
+    }
+}
+
+fn edit_save(save: &mut PlayerSave, items: &ItemNames, edit: &[&str]) -> Result<(), String> {
+    match edit {
+        ["set-position", x, y, z] => {
+            save.position = DVec3::new(parse(x)?, parse(y)?, parse(z)?);
+        }
+        ["set-health", hearts] => {
+            save.health.set_hearts(parse(hearts)?);
+        }
+        ["set-slot", index, item_name, count] => {
+            let index: usize = parse(index)?;
+            let count: u32 = parse(count)?;
+            if index >= save.inventory.len() {
+                return Err(format!(
+                    "The inventory only has {} slots",
+                    save.inventory.len()
+                ));
+            }
+
+            save.inventory[index] = if count == 0 {
+                ItemStack::default()
+            } else {
+                items.item_stack(item_name, count)?
+            };
+        }
+        ["give", item_name] => give(save, items, item_name, 1)?,
+        ["give", item_name, count] => give(save, items, item_name, parse(count)?)?,
+        ["clear-inventory"] => {
+            for item_stack in save.inventory.iter_mut() {
+                *item_stack = ItemStack::default();
+            }
+        }
+        _ => return Err(format!("Unknown edit: '{}'", edit.join(" "))),
+    }
+
+    Ok(())
+}
+
+fn give(
+    save: &mut PlayerSave,
+    items: &ItemNames,
+    item_name: &str,
+    count: u32,
+) -> Result<(), String> {
+    let max_stack_size = items.max_stack_size(item_name)?;
+
+    let mut remaining = count;
+    for item_stack in save.inventory.iter_mut() {
+        if remaining == 0 {
+            break;
+        }
+        if !item_stack.is_empty() {
+            continue;
+        }
+
+        let size = remaining.min(max_stack_size);
+        *item_stack = items.item_stack(item_name, size)?;
+        remaining -= size;
+    }
+
+    if remaining != 0 {
+        return Err(format!(
+            "The inventory is full, {} {} could not be added",
+            remaining, item_name
+        ));
+    }
+
+    Ok(())
+}
+
+fn print_save(save: &PlayerSave, items: &ItemNames) {
+    println!(
+        "position: {} {} {}",
+        save.position.x, save.position.y, save.position.z
+    );
+    println!("health: {}/{}", save.health.hearts(), save.health.max());
+    println!("inventory:");
+    for (index, item_stack) in save.inventory.iter().enumerate() {
+        let Some(item) = item_stack.item() else {
+            continue;
+        };
+        println!(
+            "    {:>2}: {} x{}",
+            index,
+            items.name(item.id),
+            item_stack.size()
+        );
+    }
+}
+
+fn parse<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Could not interpret '{}' as a number", value))
+}
+
+/// The ids the server assigned to each item when the world was created.
+struct ItemNames {
+    name_to_id: HashMap<String, ItemId>,
+    id_to_name: HashMap<ItemId, String>,
+}
+
+impl ItemNames {
+    fn load(conn: &rusqlite::Connection) -> Self {
+        let mut stmt = conn.prepare("SELECT id, name FROM item_ids").unwrap();
+        let name_to_id: HashMap<String, ItemId> = stmt
+            .query_map([], |row| Ok((row.get(1)?, row.get(0)?)))
+            .unwrap()
+            .map(|row| row.unwrap())
+            .collect();
+        let id_to_name = name_to_id
+            .iter()
+            .map(|(name, id)| (*id, name.clone()))
+            .collect();
+
+        Self {
+            name_to_id,
+            id_to_name,
+        }
+    }
+
+    fn name(&self, item_id: ItemId) -> &str {
+        self.id_to_name
+            .get(&item_id)
+            .map(|name| name.as_str())
+            .unwrap_or("<unknown item>")
+    }
+
+    // The stack size isn't stored in the database, it is read from the item's config.
+    fn max_stack_size(&self, item_name: &str) -> Result<u32, String> {
+        let path = format!("resources/client/items/configurations/{}.json", item_name);
+        let config: serde_json::Value = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .ok_or(format!(
+                "Could not read the item config for '{}' at: {}",
+                item_name, path
+            ))?;
+
+        Ok(config["stack_size"].as_u64().unwrap_or(1) as u32)
+    }
+
+    fn item_stack(&self, item_name: &str, count: u32) -> Result<ItemStack, String> {
+        let Some(item_id) = self.name_to_id.get(item_name) else {
+            return Err(format!("There is no item by the name '{}'", item_name));
+        };
+        let max_stack_size = self.max_stack_size(item_name)?;
+
+        Ok(ItemStack::new(
+            Item::new(*item_id),
+            count.min(max_stack_size),
+            max_stack_size,
+        ))
+    }
+}
@@ -0,0 +1,174 @@
+use fmc::{
+    blocks::{BlockId, Blocks},
+    items::{Item, ItemId, ItemStack, Items},
+    players::Player,
+    prelude::*,
+    world::{BlockUpdate, WorldMap},
+};
+
+use crate::players::{EquippedItem, Inventory};
+
+use super::{ItemUses, UsableItems};
+
+pub struct BucketPlugin;
+impl Plugin for BucketPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, register_buckets).add_systems(
+            Update,
+            (fill_buckets, empty_buckets).after(super::RegisterItemUse),
+        );
+    }
+}
+
+#[derive(Component)]
+struct EmptyBucket;
+
+#[derive(Component)]
+struct WaterBucket;
+
+#[derive(Component)]
+struct BucketConfig {
+    air: BlockId,
+    // Placed by the water bucket, it spreads like any other water source.
+    water: BlockId,
+    // Water blocks that can be picked up
+    sources: Vec<BlockId>,
+    bucket: ItemId,
+    water_bucket: ItemId,
+}
+
+fn register_buckets(
+    mut commands: Commands,
+    blocks: Res<Blocks>,
+    items: Res<Items>,
+    mut usable_items: ResMut<UsableItems>,
+) {
+    let config = || BucketConfig {
+        air: blocks.get_id("air"),
+        water: blocks.get_id("surface_water"),
+        sources: vec![
+            blocks.get_id("surface_water"),
+            blocks.get_id("subsurface_water"),
+            blocks.get_id("spreading_water"),
+        ],
+        bucket: items.get_id("bucket").unwrap(),
+        water_bucket: items.get_id("water_bucket").unwrap(),
+    };
+
+    usable_items.insert(
+        items.get_id("bucket").unwrap(),
+        commands
+            .spawn((ItemUses::default(), EmptyBucket, config()))
+            .id(),
+    );
+    usable_items.insert(
+        items.get_id("water_bucket").unwrap(),
+        commands
+            .spawn((ItemUses::default(), WaterBucket, config()))
+            .id(),
+    );
+}
+
+// Swap the bucket the player is holding for another.
+fn swap_bucket(inventory: &mut Inventory, equipped_item: &EquippedItem, items: &Items, to: ItemId) {
+    let item_config = items.get_config(&to);
+    inventory[equipped_item.0] = ItemStack::new(Item::new(to), 1, item_config.max_stack_size);
+}
+
+fn fill_buckets(
+    items: Res<Items>,
+    world_map: Res<WorldMap>,
+    mut bucket_uses: Query<(&mut ItemUses, &BucketConfig), (With<EmptyBucket>, Changed<ItemUses>)>,
+    mut player_query: Query<(&mut Inventory, &EquippedItem), With<Player>>,
+    mut block_update_writer: EventWriter<BlockUpdate>,
+) {
+    let Ok((mut uses, config)) = bucket_uses.get_single_mut() else {
+        return;
+    };
+
+    for bucket_use in uses.read() {
+        let Some((block_id, block_position)) = bucket_use.block else {
+            continue;
+        };
+
+        // The water can either be what was clicked, or in front of the face that was clicked
+        // if the click went through it.
+        let source_position = if config.sources.contains(&block_id) {
+            block_position
+        } else if let Some(in_front) = bucket_use
+            .block_face
+            .map(|face| face.shift_position(block_position))
+            .filter(|position| {
+                world_map
+                    .get_block(*position)
+                    .is_some_and(|block_id| config.sources.contains(&block_id))
+            })
+        {
+            in_front
+        } else {
+            continue;
+        };
+
+        let Ok((mut inventory, equipped_item)) = player_query.get_mut(bucket_use.player_entity)
+        else {
+            continue;
+        };
+        swap_bucket(&mut inventory, equipped_item, &items, config.water_bucket);
+
+        block_update_writer.send(BlockUpdate::Change {
+            position: source_position,
+            block_id: config.air,
+            block_state: None,
+        });
+    }
+}
+
+fn empty_buckets(
+    items: Res<Items>,
+    world_map: Res<WorldMap>,
+    mut bucket_uses: Query<(&mut ItemUses, &BucketConfig), (With<WaterBucket>, Changed<ItemUses>)>,
+    mut player_query: Query<(&mut Inventory, &EquippedItem), With<Player>>,
+    mut block_update_writer: EventWriter<BlockUpdate>,
+) {
+    let Ok((mut uses, config)) = bucket_uses.get_single_mut() else {
+        return;
+    };
+
+    let blocks = Blocks::get();
+
+    for bucket_use in uses.read() {
+        let Some((block_id, block_position)) = bucket_use.block else {
+            continue;
+        };
+
+        // Same as placing a block, the water replaces what was clicked if it can, and goes in
+        // front of it if not.
+        let water_position = if blocks.get_config(&block_id).replaceable {
+            block_position
+        } else if let Some(in_front) = bucket_use
+            .block_face
+            .map(|face| face.shift_position(block_position))
+            .filter(|position| {
+                world_map
+                    .get_block(*position)
+                    .is_some_and(|block_id| blocks.get_config(&block_id).replaceable)
+            })
+        {
+            in_front
+        } else {
+            continue;
+        };
+
+        let Ok((mut inventory, equipped_item)) = player_query.get_mut(bucket_use.player_entity)
+        else {
+            continue;
+        };
+        swap_bucket(&mut inventory, equipped_item, &items, config.bucket);
+
+        block_update_writer.send(BlockUpdate::Change {
+            position: water_position,
+            block_id: config.water,
+            block_state: None,
+        });
+    }
+}
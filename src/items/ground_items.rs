@@ -1,24 +1,45 @@
+use std::collections::HashSet;
+
 use fmc::{
-    bevy::math::DVec3,
+    bevy::{app::AppExit, math::DVec3},
+    database::Database,
     items::{Item, ItemConfig, ItemId, ItemStack, Items},
-    models::{Model, ModelAnimations, ModelBundle, ModelConfig, ModelMap, ModelVisibility},
+    models::{Model, ModelAnimations, ModelBundle, ModelConfig, ModelMap, ModelVisibility, Models},
     physics::{shapes::Aabb, PhysicsBundle, Velocity},
     prelude::*,
     utils,
+    world::WorldMap,
 };
+use serde::{Deserialize, Serialize};
 
-use crate::players::Inventory;
+use crate::{players::Inventory, settings::Settings};
 
 pub struct GroundItemPlugin;
 impl Plugin for GroundItemPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Update, pick_up_items);
+        app.insert_resource(SaveTimer(Timer::from_seconds(60.0, TimerMode::Repeating)))
+            .add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                (
+                    pick_up_items,
+                    despawn_old_items,
+                    (load_ground_items, unload_ground_items, save_ground_items).chain(),
+                ),
+            )
+            // The final save is done last, so it sees the exit no matter when it was sent
+            // during the frame.
+            .add_systems(
+                Last,
+                save_ground_items_on_exit.run_if(on_event::<AppExit>()),
+            );
     }
 }
 
 #[derive(Bundle)]
 pub struct GroundItemBundle {
     dropped_item: DroppedItem,
+    age: ItemAge,
     model_bundle: ModelBundle,
     physics_bundle: PhysicsBundle,
 }
@@ -31,12 +52,31 @@ impl GroundItemBundle {
         count: u32,
         position: DVec3,
     ) -> Self {
-        let dropped_item = DroppedItem(ItemStack::new(
-            Item::new(item_id),
-            count,
-            item_config.max_stack_size,
-        ));
+        let item_stack = ItemStack::new(Item::new(item_id), count, item_config.max_stack_size);
+
+        let random = rand::random::<f64>() * std::f64::consts::TAU;
+        let (velocity_x, velocity_z) = random.sin_cos();
+
+        let translation = position + DVec3::splat(0.5) - DVec3::from(model_config.aabb.center);
 
+        Self::build(
+            item_stack,
+            item_config,
+            model_config,
+            translation,
+            DVec3::new(velocity_x, 5.5, velocity_z),
+            0.0,
+        )
+    }
+
+    fn build(
+        item_stack: ItemStack,
+        item_config: &ItemConfig,
+        model_config: &ModelConfig,
+        translation: DVec3,
+        velocity: DVec3,
+        age: f32,
+    ) -> Self {
         // TODO: This won't work if the model must be scaled up
         //
         // We want dropped items to have a uniform size. If the model's width is
@@ -48,11 +88,6 @@ impl GroundItemBundle {
         let y_scale = HALF_SIZE * 1.5 / aabb.half_extents.y;
         let scale = if xz_scale < 1.0 { xz_scale } else { y_scale };
 
-        let random = rand::random::<f64>() * std::f64::consts::TAU;
-        let (velocity_x, velocity_z) = random.sin_cos();
-
-        let translation = position + DVec3::splat(0.5) - DVec3::from(aabb.center);
-
         let model_bundle = ModelBundle {
             model: Model::Asset(item_config.model_id),
             animations: ModelAnimations::default(),
@@ -66,7 +101,7 @@ impl GroundItemBundle {
         };
 
         let physics_bundle = PhysicsBundle {
-            velocity: Velocity(DVec3::new(velocity_x, 5.5, velocity_z)),
+            velocity: Velocity(velocity),
             aabb: Aabb {
                 //Offset the aabb slightly downwards to make the item float for clients.
                 center: DVec3::new(0.0, -0.1, 0.0),
@@ -76,7 +111,8 @@ impl GroundItemBundle {
         };
 
         return GroundItemBundle {
-            dropped_item,
+            dropped_item: DroppedItem(item_stack),
+            age: ItemAge(age),
             model_bundle,
             physics_bundle,
         };
@@ -87,6 +123,260 @@ impl GroundItemBundle {
 #[derive(Component, Deref, DerefMut)]
 struct DroppedItem(pub ItemStack);
 
+// Seconds the item has been on the ground while its chunk was loaded.
+#[derive(Component)]
+struct ItemAge(f32);
+
+/// The format ground items are saved as in the database.
+#[derive(Serialize, Deserialize)]
+struct GroundItemSave {
+    item_stack: ItemStack,
+    translation: DVec3,
+    age: f32,
+}
+
+/// Chunks that have items stored in the database that have not yet been spawned.
+#[derive(Resource, Default)]
+struct UnloadedGroundItems(HashSet<IVec3>);
+
+#[derive(Resource, Deref, DerefMut)]
+struct SaveTimer(Timer);
+
+fn setup(mut commands: Commands, database: Res<Database>) {
+    let conn = database.get_connection();
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS ground_items (
+            x INTEGER NOT NULL,
+            y INTEGER NOT NULL,
+            z INTEGER NOT NULL,
+            save TEXT NOT NULL
+        )",
+        [],
+    )
+    .unwrap();
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS ground_items_chunk ON ground_items (x, y, z)",
+        [],
+    )
+    .unwrap();
+
+    // Everything in the database is unspawned at startup.
+    let mut stmt = conn
+        .prepare("SELECT DISTINCT x, y, z FROM ground_items")
+        .unwrap();
+    let chunk_positions = stmt
+        .query_map([], |row| {
+            Ok(IVec3::new(row.get(0)?, row.get(1)?, row.get(2)?))
+        })
+        .unwrap()
+        .map(|position| position.unwrap())
+        .collect();
+
+    commands.insert_resource(UnloadedGroundItems(chunk_positions));
+}
+
+fn despawn_old_items(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    time: Res<Time>,
+    mut ground_items: Query<(Entity, &mut ItemAge)>,
+) {
+    for (entity, mut age) in ground_items.iter_mut() {
+        age.0 += time.delta_seconds();
+
+        if settings.item_despawn_time != 0 && age.0 > settings.item_despawn_time as f32 {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+// Spawn the stored items of chunks as they are loaded. The rows are kept until the next save
+// replaces them, so the items aren't lost if the server stops before then.
+fn load_ground_items(
+    mut commands: Commands,
+    database: Res<Database>,
+    world_map: Res<WorldMap>,
+    items: Res<Items>,
+    models: Res<Models>,
+    mut unloaded: ResMut<UnloadedGroundItems>,
+) {
+    if unloaded.0.is_empty() {
+        return;
+    }
+
+    let loaded: Vec<IVec3> = unloaded
+        .0
+        .iter()
+        .filter(|chunk_position| world_map.contains_chunk(chunk_position))
+        .cloned()
+        .collect();
+
+    if loaded.is_empty() {
+        return;
+    }
+
+    let conn = database.get_connection();
+    let mut select = conn
+        .prepare("SELECT save FROM ground_items WHERE x = ? AND y = ? AND z = ?")
+        .unwrap();
+
+    for chunk_position in loaded {
+        unloaded.0.remove(&chunk_position);
+
+        let params = rusqlite::params![chunk_position.x, chunk_position.y, chunk_position.z];
+        let saves: Vec<String> = select
+            .query_map(params, |row| row.get(0))
+            .unwrap()
+            .map(|save| save.unwrap())
+            .collect();
+
+        for save in saves {
+            let save: GroundItemSave = serde_json::from_str(&save).unwrap();
+            let Some(item) = save.item_stack.item() else {
+                continue;
+            };
+            let item_config = items.get_config(&item.id);
+
+            commands.spawn(GroundItemBundle::build(
+                save.item_stack.clone(),
+                item_config,
+                models.get_by_id(item_config.model_id),
+                save.translation,
+                DVec3::ZERO,
+                save.age,
+            ));
+        }
+    }
+}
+
+// Store and despawn items that are left behind in chunks that are no longer loaded.
+fn unload_ground_items(
+    mut commands: Commands,
+    database: Res<Database>,
+    world_map: Res<WorldMap>,
+    mut unloaded: ResMut<UnloadedGroundItems>,
+    ground_items: Query<(Entity, &DroppedItem, &ItemAge, &Transform)>,
+) {
+    let mut conn = None;
+
+    for (entity, dropped_item, age, transform) in ground_items.iter() {
+        let chunk_position =
+            utils::world_position_to_chunk_position(transform.translation.floor().as_ivec3());
+        if world_map.contains_chunk(&chunk_position) {
+            continue;
+        }
+
+        let conn = conn.get_or_insert_with(|| database.get_connection());
+
+        // The rows stored when the chunk was last saved or loaded are replaced by the items
+        // that are in it now.
+        if unloaded.0.insert(chunk_position) {
+            conn.execute(
+                "DELETE FROM ground_items WHERE x = ? AND y = ? AND z = ?",
+                rusqlite::params![chunk_position.x, chunk_position.y, chunk_position.z],
+            )
+            .unwrap();
+        }
+
+        insert_item(conn, chunk_position, dropped_item, age, transform);
+        commands.entity(entity).despawn();
+    }
+}
+
+// Items in loaded chunks are saved periodically so they survive a restart.
+fn save_ground_items(
+    database: Res<Database>,
+    time: Res<Time>,
+    world_map: Res<WorldMap>,
+    unloaded: Res<UnloadedGroundItems>,
+    mut save_timer: ResMut<SaveTimer>,
+    ground_items: Query<(&DroppedItem, &ItemAge, &Transform)>,
+) {
+    if save_timer.tick(time.delta()).just_finished() {
+        store_ground_items(&database, &world_map, &unloaded, &ground_items);
+    }
+}
+
+fn save_ground_items_on_exit(
+    database: Res<Database>,
+    world_map: Res<WorldMap>,
+    unloaded: Res<UnloadedGroundItems>,
+    ground_items: Query<(&DroppedItem, &ItemAge, &Transform)>,
+) {
+    store_ground_items(&database, &world_map, &unloaded, &ground_items);
+}
+
+// The stored rows of loaded chunks are replaced by the items that are in them. Items in chunks
+// that are no longer loaded have already been stored by 'unload_ground_items', they are only
+// waiting to be despawned.
+fn store_ground_items(
+    database: &Database,
+    world_map: &WorldMap,
+    unloaded: &UnloadedGroundItems,
+    ground_items: &Query<(&DroppedItem, &ItemAge, &Transform)>,
+) {
+    let mut conn = database.get_connection();
+    let transaction = conn.transaction().unwrap();
+
+    let stored: Vec<IVec3> = transaction
+        .prepare("SELECT DISTINCT x, y, z FROM ground_items")
+        .unwrap()
+        .query_map([], |row| {
+            Ok(IVec3::new(row.get(0)?, row.get(1)?, row.get(2)?))
+        })
+        .unwrap()
+        .map(|position| position.unwrap())
+        .collect();
+
+    for chunk_position in stored {
+        if unloaded.0.contains(&chunk_position) {
+            continue;
+        }
+        transaction
+            .execute(
+                "DELETE FROM ground_items WHERE x = ? AND y = ? AND z = ?",
+                rusqlite::params![chunk_position.x, chunk_position.y, chunk_position.z],
+            )
+            .unwrap();
+    }
+
+    for (dropped_item, age, transform) in ground_items.iter() {
+        let chunk_position =
+            utils::world_position_to_chunk_position(transform.translation.floor().as_ivec3());
+        if !world_map.contains_chunk(&chunk_position) {
+            continue;
+        }
+        insert_item(&transaction, chunk_position, dropped_item, age, transform);
+    }
+
+    transaction.commit().unwrap();
+}
+
+fn insert_item(
+    conn: &rusqlite::Connection,
+    chunk_position: IVec3,
+    dropped_item: &DroppedItem,
+    age: &ItemAge,
+    transform: &Transform,
+) {
+    let save = GroundItemSave {
+        item_stack: dropped_item.0.clone(),
+        translation: transform.translation,
+        age: age.0,
+    };
+
+    conn.execute(
+        "INSERT INTO ground_items VALUES (?,?,?,?)",
+        rusqlite::params![
+            chunk_position.x,
+            chunk_position.y,
+            chunk_position.z,
+            serde_json::to_string(&save).unwrap()
+        ],
+    )
+    .unwrap();
+}
+
 fn pick_up_items(
     mut commands: Commands,
     model_map: Res<ModelMap>,
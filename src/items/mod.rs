@@ -1,11 +1,16 @@
 use std::collections::HashMap;
 
-use fmc::{blocks::BlockId, items::ItemId, prelude::*};
+use fmc::{
+    blocks::{BlockFace, BlockId},
+    items::ItemId,
+    prelude::*,
+};
 
 pub mod crafting;
 mod ground_items;
 
 mod bread;
+mod buckets;
 mod hoes;
 mod seeds;
 
@@ -15,8 +20,10 @@ pub struct ItemPlugin;
 impl Plugin for ItemPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(UsableItems::default())
+            .insert_resource(RandomDrops::default())
             .add_plugins(ground_items::GroundItemPlugin)
             .add_plugins(crafting::CraftingPlugin)
+            .add_plugins(buckets::BucketPlugin)
             .add_plugins(hoes::HoePlugin)
             .add_plugins(seeds::SeedPlugin);
     }
@@ -28,11 +35,24 @@ pub struct RegisterItemUse;
 #[derive(Resource, Deref, DerefMut, Default)]
 pub struct UsableItems(HashMap<ItemId, Entity>);
 
+/// Items that blocks only drop some of the time when broken. They are dropped in addition to
+/// what the block config drops.
+#[derive(Resource, Deref, DerefMut, Default)]
+pub struct RandomDrops(HashMap<BlockId, RandomDrop>);
+
+pub struct RandomDrop {
+    pub item_id: ItemId,
+    /// Chance of the item dropping, from 0 to 1
+    pub chance: f32,
+}
+
 struct ItemUse {
     // Player that used the item
     player_entity: Entity,
     // Block the item was used on
     block: Option<(BlockId, IVec3)>,
+    // Face of the block that was clicked
+    block_face: Option<BlockFace>,
 }
 
 // TODO: Some items might be able to interact with multiple types of blocks. Having one
@@ -49,10 +69,16 @@ impl ItemUses {
         self.0.drain(..)
     }
 
-    pub fn push(&mut self, player_entity: Entity, block: Option<(BlockId, IVec3)>) {
+    pub fn push(
+        &mut self,
+        player_entity: Entity,
+        block: Option<(BlockId, IVec3)>,
+        block_face: Option<BlockFace>,
+    ) {
         self.0.push(ItemUse {
             player_entity,
             block,
+            block_face,
         });
     }
 }
@@ -4,23 +4,67 @@ use fmc::bevy::{
 };
 
 mod assets;
+mod backup;
+mod console;
+mod editor;
 mod items;
 mod mobs;
 mod players;
+mod preview;
 mod settings;
 mod skybox;
+mod weather;
 mod world;
 
 fn main() {
-    App::new()
-        .insert_resource(settings::Settings::load())
+    let settings = settings::Settings::load();
+
+    let mut args = std::env::args().skip(1).peekable();
+    if args.peek().is_some_and(|arg| arg == "world") {
+        args.next();
+        editor::run(&settings, args.collect());
+        return;
+    } else if args.peek().is_some_and(|arg| arg == "preview") {
+        args.next();
+        preview::run(&settings, args.collect());
+        return;
+    }
+
+    let mut pregenerate = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--restore" => {
+                let Some(backup) = args.next() else {
+                    panic!("'--restore' takes the path of a backup, or 'latest'");
+                };
+                backup::restore(&settings, &backup);
+            }
+            "--pregenerate" => {
+                let Some(radius) = args.next().and_then(|radius| radius.parse::<u32>().ok()) else {
+                    panic!("'--pregenerate' takes the radius around spawn in blocks");
+                };
+                pregenerate = Some(world::PregenerateOnStartup(radius));
+            }
+            _ => panic!("Unrecognized argument: {}", arg),
+        }
+    }
+
+    let mut app = App::new();
+    if let Some(pregenerate) = pregenerate {
+        app.insert_resource(pregenerate);
+    }
+
+    app.insert_resource(settings)
         .add_plugins(assets::ExtractBundledAssetsPlugin)
         .add_plugins(fmc::DefaultPlugins)
         //.add_plugins((FrameTimeDiagnosticsPlugin, FrameCountPlugin))
+        .add_plugins(console::ConsolePlugin)
+        .add_plugins(backup::BackupPlugin)
         .add_plugins(items::ItemPlugin)
         .add_plugins(players::PlayerPlugin)
         .add_plugins(world::WorldPlugin)
         .add_plugins(skybox::SkyPlugin)
+        .add_plugins(weather::WeatherPlugin)
         .add_plugins(mobs::MobsPlugin)
         .run();
 }
@@ -15,8 +15,9 @@ use fmc::{
 };
 
 use crate::{
-    items::{GroundItemBundle, ItemUses, RegisterItemUse, UsableItems},
+    items::{GroundItemBundle, ItemUses, RandomDrops, RegisterItemUse, UsableItems},
     players::{EquippedItem, Inventory},
+    world::blocks::BrokenBlockReplacements,
 };
 
 pub struct HandPlugin;
@@ -67,11 +68,50 @@ struct BreakingBlock {
 #[derive(Component)]
 struct BreakingBlockMarker;
 
+// Drop the items of a broken block on the ground, returns false if there was nothing to drop.
+fn drop_items(
+    commands: &mut Commands,
+    items: &Items,
+    models: &Models,
+    random_drops: &RandomDrops,
+    breaking_event: &BlockBreakingEvent,
+    tool: Option<&str>,
+) -> bool {
+    let block_config = Blocks::get().get_config(&breaking_event.block_id);
+    let mut drops = Vec::with_capacity(2);
+    if let Some(drop) = block_config.drop(tool) {
+        drops.push(drop);
+    }
+    if let Some(random_drop) = random_drops.get(&breaking_event.block_id) {
+        if rand::random::<f32>() < random_drop.chance {
+            drops.push((random_drop.item_id, 1));
+        }
+    }
+
+    let dropped = !drops.is_empty();
+    for (dropped_item_id, count) in drops {
+        let item_config = items.get_config(&dropped_item_id);
+        let model_config = models.get_by_id(item_config.model_id);
+
+        commands.spawn(GroundItemBundle::new(
+            dropped_item_id,
+            item_config,
+            model_config,
+            count,
+            breaking_event.block_position.as_dvec3(),
+        ));
+    }
+
+    return dropped;
+}
+
 // TODO: Take into account player's equipped item
 fn break_blocks(
     mut commands: Commands,
     items: Res<Items>,
     models: Res<Models>,
+    random_drops: Res<RandomDrops>,
+    replacements: Res<BrokenBlockReplacements>,
     player_equipped_item_query: Query<(&Inventory, &EquippedItem), With<Player>>,
     mut model_query: Query<(&mut Model, &mut ModelVisibility), With<BreakingBlockMarker>>,
     mut block_update_writer: EventWriter<BlockUpdate>,
@@ -108,6 +148,11 @@ fn break_blocks(
             continue;
         }
 
+        let left_behind = replacements
+            .get(&breaking_event.block_id)
+            .copied()
+            .unwrap_or_else(|| blocks.get_id("air"));
+
         if let Some(breaking_block) = being_broken.get_mut(&breaking_event.block_position) {
             if (now - breaking_block.prev_hit).as_secs_f32() > 0.05 {
                 // The interval between two clicks needs to be short in order to be counted as
@@ -142,26 +187,18 @@ fn break_blocks(
             if progress >= 1.0 {
                 block_update_writer.send(BlockUpdate::Change {
                     position: breaking_event.block_position,
-                    block_id: blocks.get_id("air"),
+                    block_id: left_behind,
                     block_state: None,
                 });
 
-                let block_config = blocks.get_config(&breaking_event.block_id);
-                let (dropped_item_id, count) =
-                    match block_config.drop(tool.map(|t| t.name.as_str())) {
-                        Some(drop) => drop,
-                        None => continue,
-                    };
-                let item_config = items.get_config(&dropped_item_id);
-                let model_config = models.get_by_id(item_config.model_id);
-
-                commands.spawn(GroundItemBundle::new(
-                    dropped_item_id,
-                    item_config,
-                    model_config,
-                    count,
-                    breaking_event.block_position.as_dvec3(),
-                ));
+                drop_items(
+                    &mut commands,
+                    &items,
+                    &models,
+                    &random_drops,
+                    breaking_event,
+                    tool.map(|t| t.name.as_str()),
+                );
             } else if prev_progress < 0.9 && progress > 0.9 {
                 *material_parallax_texture = Some("blocks/breaking_9.png".to_owned());
             } else if prev_progress < 0.8 && progress > 0.8 {
@@ -185,25 +222,20 @@ fn break_blocks(
             // Blocks that break instantly
             block_update_writer.send(BlockUpdate::Change {
                 position: breaking_event.block_position,
-                block_id: blocks.get_id("air"),
+                block_id: left_behind,
                 block_state: None,
             });
 
-            let block_config = blocks.get_config(&breaking_event.block_id);
-            let (dropped_item_id, count) = match block_config.drop(tool.map(|t| t.name.as_str())) {
-                Some(drop) => drop,
-                None => continue,
-            };
-            let item_config = items.get_config(&dropped_item_id);
-            let model_config = models.get_by_id(item_config.model_id);
-
-            commands.spawn(GroundItemBundle::new(
-                dropped_item_id,
-                item_config,
-                model_config,
-                count,
-                breaking_event.block_position.as_dvec3(),
-            ));
+            if !drop_items(
+                &mut commands,
+                &items,
+                &models,
+                &random_drops,
+                breaking_event,
+                tool.map(|t| t.name.as_str()),
+            ) {
+                continue;
+            }
 
             // Guard against the block being broken again on the same tick
             being_broken.insert(
@@ -567,6 +599,7 @@ fn handle_right_clicks(
             uses.push(
                 right_click.player_entity,
                 block_hit.map(|(block_position, block_id, _, _)| (block_id, block_position)),
+                block_hit.map(|(_, _, block_face, _)| block_face),
             );
         }
 
@@ -60,6 +60,20 @@ impl Default for Health {
 }
 
 impl Health {
+    pub fn hearts(&self) -> u32 {
+        self.hearts
+    }
+
+    pub fn max(&self) -> u32 {
+        self.max
+    }
+
+    /// Set the health directly, clamped to the max. Does not notify the player, only meant to be
+    /// used on saves.
+    pub fn set_hearts(&mut self, hearts: u32) {
+        self.hearts = hearts.min(self.max);
+    }
+
     pub fn take_damage(&mut self, damage: u32) -> messages::InterfaceNodeVisibilityUpdate {
         let old_hearts = self.hearts;
         self.hearts = self.hearts.saturating_sub(damage);
@@ -16,7 +16,8 @@ use serde::{Deserialize, Serialize};
 
 use crate::{items::crafting::CraftingGrid, world::WorldProperties};
 
-use self::health::{Health, HealthBundle};
+pub use self::health::Health;
+use self::health::HealthBundle;
 
 mod hand;
 mod health;
@@ -124,18 +125,24 @@ impl From<PlayerSave> for PlayerBundle {
 /// The format the player is saved as in the database.
 #[derive(Serialize, Deserialize)]
 pub struct PlayerSave {
-    position: DVec3,
-    camera_position: DVec3,
-    camera_rotation: DQuat,
-    inventory: Inventory,
-    equipment: Equipment,
-    health: Health,
+    pub position: DVec3,
+    pub camera_position: DVec3,
+    pub camera_rotation: DQuat,
+    pub inventory: Inventory,
+    pub equipment: Equipment,
+    pub health: Health,
 }
 
 impl PlayerSave {
     fn save(&self, username: &str, database: &Database) {
-        let conn = database.get_connection();
+        self.write(username, &database.get_connection());
+    }
+
+    fn load(username: &str, database: &Database) -> Option<Self> {
+        Self::read(username, &database.get_connection())
+    }
 
+    pub fn write(&self, username: &str, conn: &rusqlite::Connection) {
         let mut stmt = conn
             .prepare("INSERT OR REPLACE INTO players VALUES (?,?)")
             .unwrap();
@@ -144,9 +151,7 @@ impl PlayerSave {
         stmt.execute(rusqlite::params![username, json]).unwrap();
     }
 
-    fn load(username: &str, database: &Database) -> Option<Self> {
-        let conn = database.get_connection();
-
+    pub fn read(username: &str, conn: &rusqlite::Connection) -> Option<Self> {
         let mut stmt = conn
             .prepare("SELECT save FROM players WHERE name = ?")
             .unwrap();
@@ -165,6 +170,21 @@ impl PlayerSave {
             return None;
         };
     }
+
+    /// Remove the save, the player will be treated as new the next time they join.
+    pub fn delete(username: &str, conn: &rusqlite::Connection) -> bool {
+        let mut stmt = conn.prepare("DELETE FROM players WHERE name = ?").unwrap();
+        stmt.execute([username]).unwrap() > 0
+    }
+
+    /// Names of all players that have a save.
+    pub fn list(conn: &rusqlite::Connection) -> Vec<String> {
+        let mut stmt = conn.prepare("SELECT name FROM players").unwrap();
+        stmt.query_map([], |row| row.get(0))
+            .unwrap()
+            .map(|name| name.unwrap())
+            .collect()
+    }
 }
 
 fn add_players(
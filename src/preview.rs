@@ -0,0 +1,534 @@
+use std::collections::HashMap;
+
+use fmc::{
+    blocks::{BlockId, Blocks},
+    prelude::*,
+    world::{chunk::Chunk, TerrainGenerator},
+};
+
+use crate::{
+    assets,
+    settings::Settings,
+    world::{Earth, Flat, Void, WorldProperties, WorldType},
+};
+
+const USAGE: &str = "\
+Usage: server preview <command> [options]
+
+Commands:
+    map <x1> <z1> <x2> <z2>                     Top-down map of the area between the two corners,
+                                                extended to whole chunk columns
+    slice x <x> <z1> <z2>                       Vertical slice along the z axis at x
+    slice z <z> <x1> <x2>                       Vertical slice along the x axis at z
+
+Options:
+    --seed <seed>                               Seed of the terrain, default 0
+    --bottom <y>                                Lowest height included, default -64
+    --top <y>                                   Highest height included, default 192
+    --output <path>                             Where to write the png
+
+The terrain is generated for the type of the world if it has been created, otherwise for the
+world type in the settings.";
+
+// Colors of the air in slices and of columns with nothing in them on maps.
+const SKY_COLOR: [u8; 3] = [160, 200, 250];
+const VOID_COLOR: [u8; 3] = [20, 20, 30];
+
+struct Options {
+    seed: i32,
+    bottom: i32,
+    top: i32,
+    output: Option<String>,
+}
+
+enum Preview {
+    Map {
+        min: IVec2,
+        max: IVec2,
+    },
+    // The slice follows the x axis if 'along_x', otherwise the z axis. 'position' is where it
+    // is on the other axis.
+    Slice {
+        along_x: bool,
+        position: i32,
+        from: i32,
+        to: i32,
+    },
+}
+
+/// Render the terrain to a png without starting the server, for tuning the terrain generation.
+pub fn run(settings: &Settings, args: Vec<String>) {
+    let args: Vec<&str> = args.iter().map(|arg| arg.as_str()).collect();
+    let (preview, options) = match parse_args(&args) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            return;
+        }
+    };
+
+    let world_type = if std::path::Path::new(&settings.database_path).exists() {
+        let conn = rusqlite::Connection::open(&settings.database_path).unwrap();
+        WorldProperties::read(&conn).and_then(|properties| properties.world_type)
+    } else {
+        None
+    }
+    .unwrap_or_else(|| settings.world_type.clone());
+
+    println!("Loading blocks...");
+    let blocks = assets::load_blocks();
+
+    let start = std::time::Instant::now();
+    let image = match world_type {
+        WorldType::Normal => render(&Earth::new(options.seed, blocks), &preview, &options),
+        WorldType::Island { radius } => render(
+            &Earth::island(options.seed, blocks, radius),
+            &preview,
+            &options,
+        ),
+        WorldType::Flat { layers } => render(&Flat::new(&layers, blocks), &preview, &options),
+        WorldType::Void => render(&Void::new(blocks), &preview, &options),
+    };
+    println!(
+        "Generated the '{}' terrain in {:.1}s",
+        world_type,
+        start.elapsed().as_secs_f32()
+    );
+
+    let output = options.output.unwrap_or(match preview {
+        Preview::Map { .. } => "terrain_map.png".to_owned(),
+        Preview::Slice { .. } => "terrain_slice.png".to_owned(),
+    });
+    match image.write(&output) {
+        Ok(()) => println!("Wrote {}x{} image to {}", image.width, image.height, output),
+        Err(e) => eprintln!("Failed to write the image to '{}'\nError: {}", output, e),
+    }
+}
+
+fn parse_args(args: &[&str]) -> Result<(Preview, Options), String> {
+    let (preview, mut rest) = match args {
+        ["map", x1, z1, x2, z2, rest @ ..] => {
+            let (x1, z1, x2, z2) = (parse(x1)?, parse(z1)?, parse(x2)?, parse(z2)?);
+            let preview = Preview::Map {
+                min: IVec2::new(x1, z1).min(IVec2::new(x2, z2)),
+                max: IVec2::new(x1, z1).max(IVec2::new(x2, z2)),
+            };
+            (preview, rest)
+        }
+        ["slice", axis @ ("x" | "z"), position, from, to, rest @ ..] => {
+            let (from, to): (i32, i32) = (parse(from)?, parse(to)?);
+            let preview = Preview::Slice {
+                along_x: *axis == "z",
+                position: parse(position)?,
+                from: from.min(to),
+                to: from.max(to),
+            };
+            (preview, rest)
+        }
+        [] => return Err("Missing command".to_owned()),
+        _ => return Err(format!("Unknown command: '{}'", args.join(" "))),
+    };
+
+    let mut options = Options {
+        seed: 0,
+        bottom: -64,
+        top: 192,
+        output: None,
+    };
+
+    while let [option, value, remaining @ ..] = rest {
+        match *option {
+            "--seed" => options.seed = parse(value)?,
+            "--bottom" => options.bottom = parse(value)?,
+            "--top" => options.top = parse(value)?,
+            "--output" => options.output = Some(value.to_string()),
+            _ => return Err(format!("Unknown option: '{}'", option)),
+        }
+        rest = remaining;
+    }
+
+    if let [option] = rest {
+        return Err(format!("The option '{}' is missing its value", option));
+    }
+
+    if options.bottom >= options.top {
+        return Err("The bottom must be below the top".to_owned());
+    }
+
+    Ok((preview, options))
+}
+
+fn parse<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Could not interpret '{}' as a number", value))
+}
+
+struct Image {
+    width: u32,
+    height: u32,
+    // Rgb, row by row from the top
+    pixels: Vec<u8>,
+}
+
+impl Image {
+    fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![0; (width * height * 3) as usize],
+        }
+    }
+
+    fn set(&mut self, x: u32, y: u32, color: [u8; 3]) {
+        let index = ((y * self.width + x) * 3) as usize;
+        self.pixels[index..index + 3].copy_from_slice(&color);
+    }
+
+    fn write(&self, path: &str) -> Result<(), String> {
+        let file = std::fs::File::create(path).map_err(|e| e.to_string())?;
+        let mut encoder = png::Encoder::new(std::io::BufWriter::new(file), self.width, self.height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .write_header()
+            .and_then(|mut writer| writer.write_image_data(&self.pixels))
+            .map_err(|e| e.to_string())
+    }
+}
+
+fn render<G: TerrainGenerator + Sync>(
+    generator: &G,
+    preview: &Preview,
+    options: &Options,
+) -> Image {
+    match *preview {
+        Preview::Map { min, max } => render_map(generator, min, max, options),
+        Preview::Slice {
+            along_x,
+            position,
+            from,
+            to,
+        } => render_slice(generator, along_x, position, from, to, options),
+    }
+}
+
+// Generate the chunks at the positions on all cpu cores.
+fn generate_parallel<G, T>(
+    generator: &G,
+    positions: &[IVec3],
+    process: impl Fn(IVec3, Chunk) -> T + Sync,
+) -> Vec<T>
+where
+    G: TerrainGenerator + Sync,
+    T: Send,
+{
+    let threads = std::thread::available_parallelism()
+        .map(|threads| threads.get())
+        .unwrap_or(1);
+    let per_thread = positions.len().div_ceil(threads).max(1);
+
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = positions
+            .chunks(per_thread)
+            .map(|positions| {
+                let process = &process;
+                scope.spawn(move || {
+                    positions
+                        .iter()
+                        .map(|position| process(*position, generator.generate_chunk(*position)))
+                        .collect::<Vec<T>>()
+                })
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    })
+}
+
+fn get_block(chunk: &Chunk, x: usize, y: usize, z: usize) -> BlockId {
+    if chunk.is_uniform() {
+        chunk[0]
+    } else {
+        chunk[[x, y, z]]
+    }
+}
+
+// The top of a block column as seen from above.
+#[derive(Clone, Copy)]
+struct Surface {
+    block: BlockId,
+    height: i32,
+    // Height of the first block under the liquid if the surface is a liquid.
+    floor: Option<i32>,
+}
+
+impl Surface {
+    fn is_complete(&self, blocks: &Blocks) -> bool {
+        !is_liquid(blocks, self.block) || self.floor.is_some()
+    }
+}
+
+// Matched by name the same way as in 'block_color'.
+fn is_liquid(blocks: &Blocks, block_id: BlockId) -> bool {
+    let name = &blocks.get_config(&block_id).name;
+    name.contains("water") || name.contains("lava")
+}
+
+fn render_map<G: TerrainGenerator + Sync>(
+    generator: &G,
+    min: IVec2,
+    max: IVec2,
+    options: &Options,
+) -> Image {
+    let blocks = Blocks::get();
+    let air = blocks.get_id("air");
+
+    let chunk_size = Chunk::SIZE as i32;
+    let min_chunk = min.div_euclid(IVec2::splat(chunk_size)) * chunk_size;
+    let max_chunk = max.div_euclid(IVec2::splat(chunk_size)) * chunk_size;
+    let top = options.top.div_euclid(chunk_size) * chunk_size;
+
+    let mut column_positions = Vec::new();
+    for x in (min_chunk.x..=max_chunk.x).step_by(Chunk::SIZE) {
+        for z in (min_chunk.y..=max_chunk.y).step_by(Chunk::SIZE) {
+            column_positions.push(IVec3::new(x, top, z));
+        }
+    }
+    println!("Generating {} chunk columns...", column_positions.len());
+
+    // Chunks are generated from the top down until the surface of every block column is found.
+    let columns = generate_parallel(generator, &column_positions, |position, mut chunk| {
+        let mut surfaces: Vec<Option<Surface>> = vec![None; Chunk::SIZE * Chunk::SIZE];
+        let mut chunk_y = position.y;
+        loop {
+            for x in 0..Chunk::SIZE {
+                for z in 0..Chunk::SIZE {
+                    let surface = &mut surfaces[x << 4 | z];
+                    for y in (0..Chunk::SIZE).rev() {
+                        if surface.is_some_and(|surface| surface.is_complete(blocks)) {
+                            break;
+                        }
+
+                        let block = get_block(&chunk, x, y, z);
+                        let height = chunk_y + y as i32;
+                        match surface {
+                            None if block != air => {
+                                *surface = Some(Surface {
+                                    block,
+                                    height,
+                                    floor: None,
+                                })
+                            }
+                            Some(surface) if block != air && !is_liquid(blocks, block) => {
+                                surface.floor = Some(height)
+                            }
+                            _ => (),
+                        }
+                    }
+                }
+            }
+
+            chunk_y -= chunk_size;
+            let done = surfaces
+                .iter()
+                .all(|surface| surface.is_some_and(|surface| surface.is_complete(blocks)));
+            if done || chunk_y + chunk_size <= options.bottom {
+                break;
+            }
+            chunk = generator.generate_chunk(IVec3::new(position.x, chunk_y, position.z));
+        }
+        surfaces
+    });
+
+    let width = (max_chunk.x - min_chunk.x + chunk_size) as u32;
+    let height = (max_chunk.y - min_chunk.y + chunk_size) as u32;
+
+    // Surface of each block column, indexed by image position.
+    let mut surfaces = vec![None; (width * height) as usize];
+    for (position, column) in column_positions.iter().zip(columns) {
+        let offset = IVec2::new(position.x, position.z) - min_chunk;
+        for (index, surface) in column.into_iter().enumerate() {
+            let x = offset.x as u32 + (index >> 4) as u32;
+            let z = offset.y as u32 + (index & 0xf) as u32;
+            surfaces[(z * width + x) as usize] = surface;
+        }
+    }
+
+    let mut colors = BlockColors::default();
+    let mut image = Image::new(width, height);
+    for z in 0..height {
+        for x in 0..width {
+            let Some(surface) = surfaces[(z * width + x) as usize] else {
+                image.set(x, z, VOID_COLOR);
+                continue;
+            };
+
+            let mut color = colors.get(blocks, surface.block);
+
+            // Darker the lower the terrain
+            let relative_height =
+                (surface.height - options.bottom) as f32 / (options.top - options.bottom) as f32;
+            let mut brightness = 0.6 + 0.5 * relative_height.clamp(0.0, 1.0);
+
+            if let Some(floor) = surface.floor {
+                // Deeper liquid is darker
+                brightness *= 1.0 - ((surface.height - floor) as f32 / 32.0).min(0.6);
+            } else if z > 0 {
+                // Slopes facing north are lit, and those facing south are in shadow.
+                if let Some(north) = surfaces[((z - 1) * width + x) as usize] {
+                    let slope = (surface.height - north.height).clamp(-4, 4) as f32;
+                    brightness += slope * 0.05;
+                }
+            }
+
+            for channel in color.iter_mut() {
+                *channel = (*channel as f32 * brightness).clamp(0.0, 255.0) as u8;
+            }
+            image.set(x, z, color);
+        }
+    }
+
+    return image;
+}
+
+fn render_slice<G: TerrainGenerator + Sync>(
+    generator: &G,
+    along_x: bool,
+    position: i32,
+    from: i32,
+    to: i32,
+    options: &Options,
+) -> Image {
+    let blocks = Blocks::get();
+    let air = blocks.get_id("air");
+
+    let chunk_size = Chunk::SIZE as i32;
+    let to_world = |horizontal: i32, y: i32| {
+        if along_x {
+            IVec3::new(horizontal, y, position)
+        } else {
+            IVec3::new(position, y, horizontal)
+        }
+    };
+
+    let mut chunk_positions = Vec::new();
+    for horizontal in
+        (from.div_euclid(chunk_size)..=to.div_euclid(chunk_size)).map(|chunk| chunk * chunk_size)
+    {
+        for y in (options.bottom.div_euclid(chunk_size)..=options.top.div_euclid(chunk_size))
+            .map(|chunk| chunk * chunk_size)
+        {
+            let world_position = to_world(horizontal, y);
+            chunk_positions.push(world_position.div_euclid(IVec3::splat(chunk_size)) * chunk_size);
+        }
+    }
+    println!("Generating {} chunks...", chunk_positions.len());
+
+    // Only the blocks in the plane of the slice are kept, indexed by horizontal offset, then
+    // height.
+    let plane_offset = position.rem_euclid(chunk_size) as usize;
+    let planes = generate_parallel(generator, &chunk_positions, |_, chunk| {
+        let mut plane = Vec::with_capacity(Chunk::SIZE * Chunk::SIZE);
+        for horizontal in 0..Chunk::SIZE {
+            for y in 0..Chunk::SIZE {
+                plane.push(if along_x {
+                    get_block(&chunk, horizontal, y, plane_offset)
+                } else {
+                    get_block(&chunk, plane_offset, y, horizontal)
+                });
+            }
+        }
+        plane
+    });
+
+    let width = (to - from + 1) as u32;
+    let height = (options.top - options.bottom + 1) as u32;
+
+    let mut colors = BlockColors::default();
+    let mut image = Image::new(width, height);
+    for (chunk_position, plane) in chunk_positions.iter().zip(planes) {
+        let chunk_horizontal = if along_x {
+            chunk_position.x
+        } else {
+            chunk_position.z
+        };
+
+        for (index, block) in plane.into_iter().enumerate() {
+            let horizontal = chunk_horizontal + (index / Chunk::SIZE) as i32;
+            let y = chunk_position.y + (index % Chunk::SIZE) as i32;
+            if !(from..=to).contains(&horizontal) || !(options.bottom..=options.top).contains(&y) {
+                continue;
+            }
+
+            let color = if block == air {
+                SKY_COLOR
+            } else {
+                colors.get(blocks, block)
+            };
+            // The image starts at the top
+            image.set((horizontal - from) as u32, (options.top - y) as u32, color);
+        }
+    }
+
+    return image;
+}
+
+#[derive(Default)]
+struct BlockColors {
+    colors: HashMap<BlockId, [u8; 3]>,
+}
+
+impl BlockColors {
+    fn get(&mut self, blocks: &Blocks, block_id: BlockId) -> [u8; 3] {
+        *self.colors.entry(block_id).or_insert_with(|| {
+            let name = &blocks.get_config(&block_id).name;
+            block_color(name)
+        })
+    }
+}
+
+fn block_color(name: &str) -> [u8; 3] {
+    match name {
+        "grass" => [95, 159, 53],
+        "dirt" | "soil" => [134, 96, 67],
+        "sand" => [219, 207, 163],
+        "stone" => [125, 125, 125],
+        "coal_ore" => [60, 60, 60],
+        "iron_ore" => [170, 140, 120],
+        "copper_ore" => [180, 110, 70],
+        "leaves" => [56, 118, 29],
+        "birch_leaves" => [80, 140, 50],
+        "spruce_leaves" => [35, 80, 35],
+        "oak" => [102, 81, 51],
+        "birch" => [220, 220, 210],
+        "spruce" => [70, 50, 30],
+        "oak_planks" | "crafting_table" => [162, 130, 78],
+        "birch_planks" => [200, 180, 125],
+        "spruce_planks" => [95, 66, 38],
+        "torch" => [255, 200, 80],
+        "tall_grass" => [105, 170, 60],
+        "poppy" => [200, 30, 30],
+        "dandelion" => [240, 210, 40],
+        "reeds" => [110, 150, 60],
+        "dead_bush" => [125, 90, 50],
+        "seaweed" => [40, 110, 60],
+        "snow" => [240, 244, 250],
+        "ice" => [150, 190, 240],
+        name if name.starts_with("wheat") => [200, 180, 80],
+        name if name.contains("water") => [63, 118, 228],
+        name if name.contains("lava") => [230, 90, 20],
+        // Blocks without a color get one made from their name, so they are at least
+        // distinguishable.
+        name => {
+            let hash = name.bytes().fold(0x811c9dc5u32, |hash, byte| {
+                (hash ^ byte as u32).wrapping_mul(0x01000193)
+            });
+            let [r, g, b, _] = hash.to_le_bytes();
+            [r, g, b]
+        }
+    }
+}
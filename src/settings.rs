@@ -1,5 +1,7 @@
 use fmc::prelude::*;
 
+use crate::world::{FlatLayer, WorldType};
+
 use std::io::{BufRead, BufReader};
 
 #[derive(Resource)]
@@ -12,6 +14,21 @@ pub struct Settings {
     pub pvp: bool,
     /// The max render distance the server will provide for.
     pub render_distance: u32,
+    /// Minutes between each automatic backup, 0 disables them
+    pub backup_interval: u32,
+    /// How many backups are kept before the oldest is deleted
+    pub backup_count: usize,
+    /// Directory backups are written to
+    pub backup_directory: String,
+    /// Seconds before an item dropped on the ground disappears, 0 means never
+    pub item_despawn_time: u32,
+    /// Length of a full day and night cycle in seconds
+    pub day_length: f32,
+    /// The terrain new worlds are created with, existing worlds keep the one they were created
+    /// with.
+    pub world_type: WorldType,
+    /// Chunks generated per second when pre-generating the area around spawn
+    pub pregeneration_rate: u32,
 }
 
 impl Default for Settings {
@@ -21,6 +38,13 @@ impl Default for Settings {
             seed: 0,
             pvp: false,
             render_distance: 16,
+            backup_interval: 60,
+            backup_count: 5,
+            backup_directory: "./backups".to_owned(),
+            item_despawn_time: 300,
+            day_length: 1200.0,
+            world_type: WorldType::Normal,
+            pregeneration_rate: 200,
         }
     }
 }
@@ -39,6 +63,24 @@ impl Settings {
         };
         let reader = BufReader::new(file);
 
+        // The world type is assembled from several settings
+        let mut world_type = "normal".to_owned();
+        let mut flat_layers = vec![
+            FlatLayer {
+                block: "stone".to_owned(),
+                thickness: 32,
+            },
+            FlatLayer {
+                block: "dirt".to_owned(),
+                thickness: 3,
+            },
+            FlatLayer {
+                block: "grass".to_owned(),
+                thickness: 1,
+            },
+        ];
+        let mut island_radius = 512;
+
         for (line_num, line) in reader.lines().enumerate() {
             let line = line.unwrap();
 
@@ -79,6 +121,80 @@ impl Settings {
                     });
                     server_settings.pvp = value;
                 }
+                "backup-interval" => {
+                    let value = value.parse::<u32>().unwrap_or_else(|_| {
+                        panic!(
+                            "Server property 'backup-interval' must be a positive number, cannot be: {}",
+                            value
+                        )
+                    });
+                    server_settings.backup_interval = value;
+                }
+                "backup-count" => {
+                    // There must be room for at least the backup that was just taken.
+                    let value = value
+                        .parse::<usize>()
+                        .ok()
+                        .filter(|count| *count > 0)
+                        .unwrap_or_else(|| {
+                            panic!(
+                                "Server property 'backup-count' must be a number above 0, cannot be: {}",
+                                value
+                            )
+                        });
+                    server_settings.backup_count = value;
+                }
+                "backup-directory" => {
+                    server_settings.backup_directory = value.to_owned();
+                }
+                "item-despawn-time" => {
+                    let value = value.parse::<u32>().unwrap_or_else(|_| {
+                        panic!(
+                            "Server property 'item-despawn-time' must be a positive number, cannot be: {}",
+                            value
+                        )
+                    });
+                    server_settings.item_despawn_time = value;
+                }
+                "day-length" => {
+                    let value = value.parse::<f32>().ok().filter(|v| *v > 0.0).unwrap_or_else(|| {
+                        panic!(
+                            "Server property 'day-length' must be a number above zero, cannot be: {}",
+                            value
+                        )
+                    });
+                    server_settings.day_length = value;
+                }
+                "pregeneration-rate" => {
+                    let value = value.parse::<u32>().ok().filter(|v| *v > 0).unwrap_or_else(|| {
+                        panic!(
+                            "Server property 'pregeneration-rate' must be a number above zero, cannot be: {}",
+                            value
+                        )
+                    });
+                    server_settings.pregeneration_rate = value;
+                }
+                "world-type" => {
+                    world_type = value.to_owned();
+                }
+                "flat-layers" => {
+                    flat_layers = FlatLayer::parse_list(value).unwrap_or_else(|e| {
+                        panic!(
+                            "Server property 'flat-layers' must be a list of layers like \
+                            'stone:32, dirt:3, grass', bottom layer first. {}",
+                            e
+                        )
+                    });
+                }
+                "island-radius" => {
+                    let value = value.parse::<u32>().ok().filter(|v| *v > 0).unwrap_or_else(|| {
+                        panic!(
+                            "Server property 'island-radius' must be a number above zero, cannot be: {}",
+                            value
+                        )
+                    });
+                    island_radius = value;
+                }
                 _ => {
                     panic!(
                         "Undefined setting in settings file, there is no setting with the name: {}",
@@ -88,16 +204,48 @@ impl Settings {
             }
         }
 
+        server_settings.world_type = match world_type.as_str() {
+            "normal" => WorldType::Normal,
+            "flat" => WorldType::Flat {
+                layers: flat_layers,
+            },
+            "void" => WorldType::Void,
+            "island" => WorldType::Island {
+                radius: island_radius,
+            },
+            _ => panic!(
+                "Server property 'world-type' must be one of 'normal/flat/void/island', cannot be: {}",
+                world_type
+            ),
+        };
+
         return server_settings;
     }
 
+    /// Name of the world, taken from the database file name.
+    pub fn world_name(&self) -> &str {
+        std::path::Path::new(&self.database_path)
+            .file_stem()
+            .and_then(|name| name.to_str())
+            .unwrap_or("world")
+    }
+
     // Writes a default config to the server directory.
     #[rustfmt::skip]
     fn write_default() {
         let settings = Self::default();
         let contents = String::new()
             + "#world-name = " + &settings.database_path + "\n"
-            + "#pvp = " + &settings.pvp.to_string();
+            + "#pvp = " + &settings.pvp.to_string() + "\n"
+            + "#backup-interval = " + &settings.backup_interval.to_string() + "\n"
+            + "#backup-count = " + &settings.backup_count.to_string() + "\n"
+            + "#backup-directory = " + &settings.backup_directory + "\n"
+            + "#item-despawn-time = " + &settings.item_despawn_time.to_string() + "\n"
+            + "#day-length = " + &settings.day_length.to_string() + "\n"
+            + "#world-type = normal\n"
+            + "#flat-layers = stone:32, dirt:3, grass\n"
+            + "#island-radius = 512\n"
+            + "#pregeneration-rate = " + &settings.pregeneration_rate.to_string();
 
         std::fs::write("./server_settings.txt", contents).unwrap();
     }
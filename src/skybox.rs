@@ -1,30 +1,207 @@
-use fmc::{networking::Server, prelude::*, protocol::messages};
+use fmc::{
+    bevy::{app::AppExit, log::info},
+    networking::Server,
+    players::Player,
+    prelude::*,
+    protocol::messages,
+};
+
+use crate::{
+    console::ConsoleCommand,
+    settings::Settings,
+    world::{save_world_properties, WorldProperties, WorldTime},
+};
 
 pub struct SkyPlugin;
 impl Plugin for SkyPlugin {
     fn build(&self, app: &mut App) {
-        app.insert_resource(Clock::default())
-            .add_systems(Update, day_night_cycle);
+        app.insert_resource(ClockSyncTimer(Timer::from_seconds(
+            10.0,
+            TimerMode::Repeating,
+        )))
+        // The world properties are inserted during Startup
+        .add_systems(PostStartup, setup)
+        .add_systems(
+            Update,
+            (
+                advance_clock,
+                handle_time_commands,
+                sync_clock.after(advance_clock).after(handle_time_commands),
+            ),
+        )
+        // The final write is done last, so it sees the exit no matter when it was sent during
+        // the frame.
+        .add_systems(
+            Last,
+            store_clock_on_exit
+                .run_if(on_event::<AppExit>())
+                .before(save_world_properties),
+        );
     }
 }
 
-#[derive(Resource, DerefMut, Deref)]
-struct Clock(f32);
+/// The authoritative world clock, advanced every tick and stored in the [WorldProperties].
+///
+/// time % day_length == 0, dawn
+/// time % day_length == day_length / 2, dusk
+#[derive(Resource)]
+pub struct Clock {
+    time: f64,
+    day_length: f64,
+    frozen: bool,
+    // Set when the time is changed by other means than the passage of time, the clients need to
+    // be notified even if the clock is frozen.
+    dirty: bool,
+}
+
+impl Clock {
+    /// Seconds into the current day.
+    pub fn time_of_day(&self) -> f64 {
+        self.time.rem_euclid(self.day_length)
+    }
+
+    /// How many full days have passed since the world was created.
+    pub fn day(&self) -> u64 {
+        (self.time / self.day_length) as u64
+    }
+
+    pub fn is_day(&self) -> bool {
+        self.time_of_day() < self.day_length / 2.0
+    }
+
+    pub fn is_night(&self) -> bool {
+        !self.is_day()
+    }
 
-impl Default for Clock {
-    fn default() -> Self {
-        // Start a little after the sun has risen so it's brighter.
-        Self(20.0)
+    pub fn is_frozen(&self) -> bool {
+        self.frozen
+    }
+
+    pub fn day_length(&self) -> f64 {
+        self.day_length
+    }
+
+    /// Set the time of the current day.
+    pub fn set_time_of_day(&mut self, time_of_day: f64) {
+        self.time = self.day() as f64 * self.day_length + time_of_day.rem_euclid(self.day_length);
+        self.dirty = true;
+    }
+
+    pub fn set_frozen(&mut self, frozen: bool) {
+        self.frozen = frozen;
+        self.dirty = true;
+    }
+
+    /// Angle of the sun, 0 at dawn.
+    fn sun_angle(&self) -> f32 {
+        (self.time_of_day() / self.day_length * std::f64::consts::TAU) as f32
     }
 }
 
-// time == 0, dawn
-// time == 600, dusk
-const DAY_LENGTH: f32 = 1200.0;
+#[derive(Resource, Deref, DerefMut)]
+struct ClockSyncTimer(Timer);
+
+fn setup(mut commands: Commands, settings: Res<Settings>, world_properties: Res<WorldProperties>) {
+    commands.insert_resource(Clock {
+        time: world_properties.time.elapsed,
+        day_length: settings.day_length as f64,
+        frozen: world_properties.time.frozen,
+        dirty: false,
+    });
+}
+
+fn advance_clock(time: Res<Time>, mut clock: ResMut<Clock>) {
+    if clock.frozen {
+        return;
+    }
+    clock.time += time.delta_seconds_f64();
+}
+
+fn handle_time_commands(
+    mut clock: ResMut<Clock>,
+    mut console_commands: EventReader<ConsoleCommand>,
+) {
+    for command in console_commands.read() {
+        if command.name != "time" {
+            continue;
+        }
+
+        let args: Vec<&str> = command.args.iter().map(|arg| arg.as_str()).collect();
+        match args.as_slice() {
+            [] => (),
+            ["set", "day"] => clock.set_time_of_day(20.0),
+            ["set", "night"] => {
+                let dusk = clock.day_length / 2.0;
+                clock.set_time_of_day(dusk + 20.0);
+            }
+            ["set", time] => {
+                let Ok(time) = time.parse::<f64>() else {
+                    info!("The time must be a number of seconds, 'day' or 'night'");
+                    continue;
+                };
+                clock.set_time_of_day(time);
+            }
+            ["freeze"] => clock.set_frozen(true),
+            ["unfreeze"] => clock.set_frozen(false),
+            _ => {
+                info!("Usage: time [set <seconds|day|night>|freeze|unfreeze]");
+                continue;
+            }
+        }
+
+        info!(
+            "Day {}, time {:.0}/{}{}",
+            clock.day(),
+            clock.time_of_day(),
+            clock.day_length,
+            if clock.frozen { " (frozen)" } else { "" }
+        );
+    }
+}
+
+// The clients move the sun on their own between updates. They are sent the time when they join,
+// when it is changed, and at an interval to keep them from drifting. The world properties are
+// updated at the same interval and on change.
+fn sync_clock(
+    net: Res<Server>,
+    time: Res<Time>,
+    mut clock: ResMut<Clock>,
+    mut world_properties: ResMut<WorldProperties>,
+    mut sync_timer: ResMut<ClockSyncTimer>,
+    new_players: Query<Entity, Added<Player>>,
+) {
+    sync_timer.tick(time.delta());
+
+    if sync_timer.just_finished() || clock.dirty {
+        net.broadcast(messages::Time {
+            angle: clock.sun_angle(),
+        });
+    } else {
+        for player_entity in new_players.iter() {
+            net.send_one(
+                player_entity,
+                messages::Time {
+                    angle: clock.sun_angle(),
+                },
+            );
+        }
+    }
+
+    if !sync_timer.just_finished() && !clock.dirty {
+        return;
+    }
+    clock.dirty = false;
+
+    store_clock(&clock, &mut world_properties);
+}
+
+fn store_clock_on_exit(clock: Res<Clock>, mut world_properties: ResMut<WorldProperties>) {
+    store_clock(&clock, &mut world_properties);
+}
 
-fn day_night_cycle(time: Res<Time>, net: Res<Server>) {
-    let message = messages::Time {
-        angle: time.elapsed_seconds() * std::f32::consts::TAU / DAY_LENGTH,
+fn store_clock(clock: &Clock, world_properties: &mut WorldProperties) {
+    world_properties.time = WorldTime {
+        elapsed: clock.time,
+        frozen: clock.frozen,
     };
-    net.broadcast(message);
 }
@@ -0,0 +1,280 @@
+use fmc::{
+    bevy::{app::AppExit, log::info},
+    networking::Server,
+    players::Player,
+    prelude::*,
+    protocol::messages,
+};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    console::ConsoleCommand,
+    world::{save_world_properties, BiomeMap, Precipitation, WorldProperties, WorldWeather},
+};
+
+pub struct WeatherPlugin;
+impl Plugin for WeatherPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(WeatherSyncTimer(Timer::from_seconds(
+            1.0,
+            TimerMode::Repeating,
+        )))
+        // The world properties and biome map are inserted during Startup
+        .add_systems(PostStartup, setup)
+        .add_systems(
+            Update,
+            (
+                change_weather,
+                handle_weather_commands,
+                (send_weather, save_weather)
+                    .after(change_weather)
+                    .after(handle_weather_commands),
+            ),
+        )
+        // The final write is done last, so it sees the exit no matter when it was sent during
+        // the frame.
+        .add_systems(
+            Last,
+            store_weather_on_exit
+                .run_if(on_event::<AppExit>())
+                .before(save_world_properties),
+        );
+    }
+}
+
+#[derive(Default, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum WeatherKind {
+    #[default]
+    Clear,
+    Rain,
+    Thunderstorm,
+}
+
+impl WeatherKind {
+    fn from_str(name: &str) -> Option<Self> {
+        match name {
+            "clear" => Some(Self::Clear),
+            "rain" => Some(Self::Rain),
+            "thunderstorm" | "thunder" => Some(Self::Thunderstorm),
+            _ => None,
+        }
+    }
+
+    // Range of seconds the weather lasts
+    fn duration(&self) -> std::ops::Range<f32> {
+        match self {
+            Self::Clear => 600.0..1200.0,
+            Self::Rain => 180.0..480.0,
+            Self::Thunderstorm => 120.0..300.0,
+        }
+    }
+
+    // The weather that follows, and the probability of each.
+    fn transitions(&self) -> [(WeatherKind, f32); 2] {
+        match self {
+            Self::Clear => [(Self::Rain, 0.75), (Self::Thunderstorm, 0.25)],
+            Self::Rain => [(Self::Clear, 0.7), (Self::Thunderstorm, 0.3)],
+            Self::Thunderstorm => [(Self::Rain, 0.5), (Self::Clear, 0.5)],
+        }
+    }
+
+    // Node of the weather interface that is shown for this weather
+    fn interface_node(&self) -> Option<&'static str> {
+        match self {
+            Self::Clear => None,
+            Self::Rain => Some("weather/rain"),
+            Self::Thunderstorm => Some("weather/thunderstorm"),
+        }
+    }
+}
+
+/// The weather of the world. It is the same everywhere, but biomes without precipitation stay
+/// clear.
+#[derive(Resource)]
+pub struct Weather {
+    kind: WeatherKind,
+    // Seconds until the weather changes
+    remaining: f32,
+    biome_map: BiomeMap,
+}
+
+impl Weather {
+    pub fn kind(&self) -> WeatherKind {
+        self.kind
+    }
+
+    /// Set the weather, it will last for 'duration' seconds, or a random duration if None.
+    pub fn set(&mut self, kind: WeatherKind, duration: Option<f32>) {
+        self.kind = kind;
+        self.remaining = duration.unwrap_or_else(|| rand::thread_rng().gen_range(kind.duration()));
+    }
+
+    /// The weather as experienced at a position, taking the biome into account.
+    pub fn kind_at(&self, position: IVec3) -> WeatherKind {
+        match self.biome_map.get_biome(position.x, position.z) {
+            Some(biome) if biome.precipitation == Precipitation::None => WeatherKind::Clear,
+            _ => self.kind,
+        }
+    }
+
+    /// If rain is falling at the position. Does not consider if it is sheltered.
+    pub fn is_raining_at(&self, position: IVec3) -> bool {
+        self.kind_at(position) != WeatherKind::Clear
+    }
+}
+
+#[derive(Resource, Deref, DerefMut)]
+struct WeatherSyncTimer(Timer);
+
+// The weather last sent to a player
+#[derive(Component)]
+struct ShownWeather(WeatherKind);
+
+fn setup(mut commands: Commands, world_properties: Res<WorldProperties>, biome_map: Res<BiomeMap>) {
+    let mut weather = Weather {
+        kind: world_properties.weather.kind,
+        remaining: world_properties.weather.remaining,
+        biome_map: biome_map.clone(),
+    };
+
+    // New worlds start out with a full period of clear weather
+    if weather.remaining <= 0.0 {
+        weather.set(weather.kind, None);
+    }
+
+    commands.insert_resource(weather);
+}
+
+fn change_weather(time: Res<Time>, mut weather: ResMut<Weather>) {
+    // Change detection is used to send the weather to the players, only changes to the kind of
+    // weather should trigger it.
+    weather.bypass_change_detection().remaining -= time.delta_seconds();
+    if weather.remaining > 0.0 {
+        return;
+    }
+
+    let mut roll = rand::thread_rng().gen::<f32>();
+    let mut next = weather.kind;
+    for (kind, probability) in weather.kind.transitions() {
+        next = kind;
+        if roll < probability {
+            break;
+        }
+        roll -= probability;
+    }
+
+    weather.set(next, None);
+}
+
+fn handle_weather_commands(
+    mut weather: ResMut<Weather>,
+    mut console_commands: EventReader<ConsoleCommand>,
+) {
+    for command in console_commands.read() {
+        if command.name != "weather" {
+            continue;
+        }
+
+        let args: Vec<&str> = command.args.iter().map(|arg| arg.as_str()).collect();
+        let (kind, duration) = match args.as_slice() {
+            [] => {
+                info!(
+                    "The weather is {:?} for {:.0} more seconds",
+                    weather.kind, weather.remaining
+                );
+                continue;
+            }
+            [kind] => (WeatherKind::from_str(kind), None),
+            [kind, duration] => match duration.parse::<f32>() {
+                Ok(duration) => (WeatherKind::from_str(kind), Some(duration)),
+                Err(_) => (None, None),
+            },
+            _ => (None, None),
+        };
+
+        let Some(kind) = kind else {
+            info!("Usage: weather [clear|rain|thunderstorm] [seconds]");
+            continue;
+        };
+
+        weather.set(kind, duration);
+        info!("Set the weather to {:?}", kind);
+    }
+}
+
+// Players are sent the weather of the biome they are in whenever it changes.
+fn send_weather(
+    mut commands: Commands,
+    net: Res<Server>,
+    time: Res<Time>,
+    weather: Res<Weather>,
+    mut sync_timer: ResMut<WeatherSyncTimer>,
+    mut players: Query<(Entity, &GlobalTransform, Option<&mut ShownWeather>), With<Player>>,
+) {
+    sync_timer.tick(time.delta());
+    if !sync_timer.just_finished() && !weather.is_changed() {
+        return;
+    }
+
+    for (player_entity, transform, shown_weather) in players.iter_mut() {
+        let kind = weather.kind_at(transform.translation().floor().as_ivec3());
+
+        match shown_weather {
+            Some(mut shown) if shown.0 != kind => shown.0 = kind,
+            Some(_) => continue,
+            None => {
+                commands.entity(player_entity).insert(ShownWeather(kind));
+            }
+        }
+
+        let mut node_update = messages::InterfaceNodeVisibilityUpdate::default();
+        for other in [WeatherKind::Rain, WeatherKind::Thunderstorm] {
+            let node = other.interface_node().unwrap().to_owned();
+            if other == kind {
+                node_update.set_visible(node);
+            } else {
+                node_update.set_hidden(node);
+            }
+        }
+        net.send_one(player_entity, node_update);
+
+        net.send_one(
+            player_entity,
+            messages::InterfaceVisibilityUpdate {
+                interface_path: "weather".to_owned(),
+                visible: kind != WeatherKind::Clear,
+            },
+        );
+    }
+}
+
+fn save_weather(
+    time: Res<Time>,
+    weather: Res<Weather>,
+    mut world_properties: ResMut<WorldProperties>,
+    mut save_timer: Local<f32>,
+) {
+    *save_timer += time.delta_seconds();
+
+    // The remaining time changes every tick, it is saved at an interval to not write the world
+    // properties constantly.
+    let kind_changed = world_properties.weather.kind != weather.kind;
+    if !kind_changed && *save_timer < 30.0 {
+        return;
+    }
+    *save_timer = 0.0;
+
+    store_weather(&weather, &mut world_properties);
+}
+
+fn store_weather_on_exit(weather: Res<Weather>, mut world_properties: ResMut<WorldProperties>) {
+    store_weather(&weather, &mut world_properties);
+}
+
+fn store_weather(weather: &Weather, world_properties: &mut WorldProperties) {
+    world_properties.weather = WorldWeather {
+        kind: weather.kind,
+        remaining: weather.remaining,
+    };
+}
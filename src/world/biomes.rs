@@ -1,115 +1,277 @@
-use std::collections::HashMap;
-
 use fmc::{
     blocks::{BlockId, Blocks, BLOCK_CONFIG_PATH},
     world::blueprints::{load_blueprints, Blueprint, BLUEPRINT_PATH},
 };
+use serde::Deserialize;
+
+use super::{
+    config_files::read_config_files,
+    decorations::{load_decorations, Decoration, DECORATION_PATH},
+};
 
+pub const BIOME_PATH: &str = "resources/server/biomes";
+
+#[derive(Clone)]
 pub struct Biome {
+    pub name: String,
     pub top_layer_block: BlockId,
     pub mid_layer_block: BlockId,
     pub bottom_layer_block: BlockId,
     pub surface_liquid: BlockId,
     pub sub_surface_liquid: BlockId,
+    /// Stand-in for the surface liquid where it should start flowing once it is loaded.
+    pub spreading_liquid: BlockId,
     pub air: BlockId,
     pub sand: BlockId,
     pub blueprints: Vec<Blueprint>,
+    pub decorations: Vec<Decoration>,
+    /// What falls from the sky when the weather is bad.
+    pub precipitation: Precipitation,
+    /// Multiplier for the height of the terrain, blended with the neighbouring biomes.
+    pub height_scale: f32,
+    climate: ClimateRange,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Precipitation {
+    None,
+    Rain,
+}
+
+/// The part of the climate a biome is placed in. The ranges include the start, but not the end,
+/// so biomes can share edges. The climate noise is mostly in the range -0.5..0.5.
+#[derive(Deserialize, Clone, Copy, Debug)]
+struct ClimateRange {
+    temperature: [f32; 2],
+    humidity: [f32; 2],
+}
+
+impl ClimateRange {
+    fn overlaps(&self, other: &Self) -> bool {
+        self.temperature[0] < other.temperature[1]
+            && other.temperature[0] < self.temperature[1]
+            && self.humidity[0] < other.humidity[1]
+            && other.humidity[0] < self.humidity[1]
+    }
+
+    // Distance to the closest point in the range, 0 if the climate is inside it.
+    fn distance_squared(&self, temperature: f32, humidity: f32) -> f32 {
+        fn axis_distance(range: [f32; 2], value: f32) -> f32 {
+            (range[0] - value).max(value - range[1]).max(0.0)
+        }
+
+        axis_distance(self.temperature, temperature).powi(2)
+            + axis_distance(self.humidity, humidity).powi(2)
+    }
+
+    fn contains(&self, temperature: f32, humidity: f32) -> bool {
+        (self.temperature[0]..self.temperature[1]).contains(&temperature)
+            && (self.humidity[0]..self.humidity[1]).contains(&humidity)
+    }
 }
 
+#[derive(Deserialize)]
 struct BiomeJson {
     top_layer_block: String,
     mid_layer_block: String,
     bottom_layer_block: String,
     surface_liquid: String,
     sub_surface_liquid: String,
+    spreading_liquid: String,
     air: String,
     sand: String,
     blueprints: Vec<String>,
+    #[serde(default)]
+    decorations: Vec<String>,
+    precipitation: Precipitation,
+    height_scale: f32,
+    #[serde(flatten)]
+    climate: ClimateRange,
 }
 
-// TODO: Create dynamically so it's easier to change. Should be able to add biomes between
-// intervals and error if they overlap.
+// How far apart in the climate two biomes can be while still blending their terrain together.
+// Lower values make the borders steeper.
+const BLEND_DISTANCE: f32 = 0.05;
+
+#[derive(Clone)]
 pub struct Biomes {
-    biomes: [Biome; 1],
+    biomes: Vec<Biome>,
 }
 
 impl Biomes {
+    /// Load all biomes from the biome directory. Each file is a biome, named by the file.
     pub fn load(blocks: &Blocks) -> Self {
-        // TODO: Biomes should be loaded from file, and shouldn't look like this. No sand, air =
-        // filler. Not finished because I haven't decided on the biome model yet.
-        let biome_name = "base".to_owned();
-        let base_biome = BiomeJson {
-            top_layer_block: "grass".to_owned(),
-            mid_layer_block: "dirt".to_owned(),
-            bottom_layer_block: "stone".to_owned(),
-            surface_liquid: "surface_water".to_owned(),
-            sub_surface_liquid: "subsurface_water".to_owned(),
-            air: "air".to_owned(),
-            sand: "sand".to_owned(),
-            blueprints: vec!["distribute_trees".to_owned(), "coal_ore".to_owned()],
-        };
+        let biome_jsons: Vec<(String, BiomeJson)> = read_config_files(BIOME_PATH, "biome");
+
+        let mut errors = Vec::new();
 
-        fn validate_block(biome_name: &str, block_name: &str, blocks: &Blocks) {
+        let validate_block = |errors: &mut Vec<String>, biome_name: &str, block_name: &str| {
             if !blocks.contains_block(block_name) {
-                panic!(
-                    "Startup failed while validating the biomes. The biome '{}' \
-                    references a block with the name '{}', but no block by that name exists. \
-                    Make sure a block by the same name is present at '{}'",
+                errors.push(format!(
+                    "The biome '{}' references a block with the name '{}', but no block by that \
+                    name exists. Make sure a block by the same name is present at '{}'",
                     biome_name, block_name, BLOCK_CONFIG_PATH
-                );
+                ));
+            }
+        };
+
+        let blueprints = load_blueprints(blocks);
+        let decorations = load_decorations(blocks);
+
+        for (biome_name, biome) in biome_jsons.iter() {
+            validate_block(&mut errors, biome_name, &biome.top_layer_block);
+            validate_block(&mut errors, biome_name, &biome.mid_layer_block);
+            validate_block(&mut errors, biome_name, &biome.bottom_layer_block);
+            validate_block(&mut errors, biome_name, &biome.surface_liquid);
+            validate_block(&mut errors, biome_name, &biome.sub_surface_liquid);
+            validate_block(&mut errors, biome_name, &biome.spreading_liquid);
+            validate_block(&mut errors, biome_name, &biome.air);
+            validate_block(&mut errors, biome_name, &biome.sand);
+
+            for blueprint_name in biome.blueprints.iter() {
+                if !blueprints.contains_key(blueprint_name) {
+                    errors.push(format!(
+                        "The biome '{}' depends on a blueprint by the name '{}', but no such \
+                        blueprint file exists. This is most likely the result of a missing file \
+                        at '{}', make sure it is present.",
+                        biome_name, blueprint_name, BLUEPRINT_PATH
+                    ));
+                }
+            }
+
+            for decoration_name in biome.decorations.iter() {
+                if !decorations.contains_key(decoration_name) {
+                    errors.push(format!(
+                        "The biome '{}' depends on a decoration by the name '{}', but no such \
+                        decoration file exists. This is most likely the result of a missing file \
+                        at '{}', make sure it is present.",
+                        biome_name, decoration_name, DECORATION_PATH
+                    ));
+                }
+            }
+
+            let climate = &biome.climate;
+            if climate.temperature[0] >= climate.temperature[1]
+                || climate.humidity[0] >= climate.humidity[1]
+            {
+                errors.push(format!(
+                    "The biome '{}' has an empty climate range, the start of the temperature and \
+                    humidity ranges must be less than the end.",
+                    biome_name
+                ));
             }
         }
 
-        fn validate_blueprint(
-            biome_name: &str,
-            blueprint_name: &str,
-            blueprints: &HashMap<String, Blueprint>,
-        ) {
-            if !blueprints.contains_key(blueprint_name) {
-                panic!(
-                    "Failed while validating the biomes. The biome '{}' depends on a blueprint by \
-                    the name '{}', but no such blueprint file exists. This is most likely the result of \
-                    a missing file at '{}', make sure it is present.",
-                    biome_name, blueprint_name, BLUEPRINT_PATH
-                );
+        for (index, (biome_name, biome)) in biome_jsons.iter().enumerate() {
+            for (other_name, other) in biome_jsons.iter().skip(index + 1) {
+                if biome.climate.overlaps(&other.climate) {
+                    errors.push(format!(
+                        "The climates of the biomes '{}' and '{}' overlap. A climate can only \
+                        belong to one biome.\n    {}: temperature {:?}, humidity {:?}\n    \
+                        {}: temperature {:?}, humidity {:?}",
+                        biome_name,
+                        other_name,
+                        biome_name,
+                        biome.climate.temperature,
+                        biome.climate.humidity,
+                        other_name,
+                        other.climate.temperature,
+                        other.climate.humidity,
+                    ));
+                }
             }
         }
 
-        validate_block(&biome_name, &base_biome.top_layer_block, blocks);
-        validate_block(&biome_name, &base_biome.mid_layer_block, blocks);
-        validate_block(&biome_name, &base_biome.bottom_layer_block, blocks);
-        validate_block(&biome_name, &base_biome.surface_liquid, blocks);
-        validate_block(&biome_name, &base_biome.sub_surface_liquid, blocks);
-        validate_block(&biome_name, &base_biome.air, blocks);
-        validate_block(&biome_name, &base_biome.sand, blocks);
+        if biome_jsons.is_empty() {
+            errors.push(format!(
+                "There are no biomes, at least one must be present at '{}'",
+                BIOME_PATH
+            ));
+        }
 
-        let blueprints = load_blueprints(blocks);
-        for blueprint_name in base_biome.blueprints.iter() {
-            validate_blueprint(&biome_name, blueprint_name, &blueprints);
+        if !errors.is_empty() {
+            panic!(
+                "Startup failed while validating the biomes.\n{}",
+                errors.join("\n")
+            );
         }
 
-        let base_biome = Biome {
-            top_layer_block: blocks.get_id(&base_biome.top_layer_block),
-            mid_layer_block: blocks.get_id(&base_biome.mid_layer_block),
-            bottom_layer_block: blocks.get_id(&base_biome.bottom_layer_block),
-            surface_liquid: blocks.get_id(&base_biome.surface_liquid),
-            sub_surface_liquid: blocks.get_id(&base_biome.sub_surface_liquid),
-            air: blocks.get_id(&base_biome.air),
-            sand: blocks.get_id(&base_biome.sand),
-            blueprints: base_biome
-                .blueprints
-                .iter()
-                .map(|name| blueprints[name].clone())
-                .collect(),
-        };
+        let biomes = biome_jsons
+            .into_iter()
+            .map(|(name, biome)| Biome {
+                name,
+                top_layer_block: blocks.get_id(&biome.top_layer_block),
+                mid_layer_block: blocks.get_id(&biome.mid_layer_block),
+                bottom_layer_block: blocks.get_id(&biome.bottom_layer_block),
+                surface_liquid: blocks.get_id(&biome.surface_liquid),
+                sub_surface_liquid: blocks.get_id(&biome.sub_surface_liquid),
+                spreading_liquid: blocks.get_id(&biome.spreading_liquid),
+                air: blocks.get_id(&biome.air),
+                sand: blocks.get_id(&biome.sand),
+                blueprints: biome
+                    .blueprints
+                    .iter()
+                    .map(|name| blueprints[name].clone())
+                    .collect(),
+                decorations: biome
+                    .decorations
+                    .iter()
+                    .map(|name| decorations[name].clone())
+                    .collect(),
+                precipitation: biome.precipitation,
+                height_scale: biome.height_scale,
+                climate: biome.climate,
+            })
+            .collect();
 
-        return Biomes {
-            biomes: [base_biome],
-        };
+        return Biomes { biomes };
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Biome> {
+        self.biomes.iter()
     }
 
-    // TODO: When implementing this, remember that the call sites also cheat.
-    pub fn get_biome(&self) -> &Biome {
-        return &self.biomes[0];
+    pub fn get(&self, index: usize) -> &Biome {
+        &self.biomes[index]
+    }
+
+    /// Index of the biome the climate belongs to. Climates outside of every biome use the closest
+    /// one.
+    pub fn select(&self, temperature: f32, humidity: f32) -> usize {
+        let mut closest = 0;
+        let mut closest_distance = f32::MAX;
+        for (index, biome) in self.biomes.iter().enumerate() {
+            if biome.climate.contains(temperature, humidity) {
+                return index;
+            }
+
+            let distance = biome.climate.distance_squared(temperature, humidity);
+            if distance < closest_distance {
+                closest = index;
+                closest_distance = distance;
+            }
+        }
+        return closest;
+    }
+
+    /// The height scale of the climate. It is averaged over the biomes, weighted by how close
+    /// they are to the climate, so the terrain changes gradually at the borders.
+    pub fn blended_height_scale(&self, temperature: f32, humidity: f32) -> f32 {
+        let mut total_weight = 0.0;
+        let mut height_scale = 0.0;
+        for biome in self.biomes.iter() {
+            let distance = biome.climate.distance_squared(temperature, humidity);
+            let weight = (-distance / BLEND_DISTANCE.powi(2)).exp();
+            total_weight += weight;
+            height_scale += biome.height_scale * weight;
+        }
+
+        if total_weight < f32::EPSILON {
+            // Too far from every biome for the weights to be meaningful.
+            return self.get(self.select(temperature, humidity)).height_scale;
+        }
+
+        return height_scale / total_weight;
     }
 }
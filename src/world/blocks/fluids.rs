@@ -0,0 +1,1969 @@
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    ops::{Index, IndexMut},
+    time::Duration,
+};
+
+use fmc::{
+    bevy::{app::AppExit, ecs::system::EntityCommands, math::DVec3},
+    blocks::{BlockData, BlockFace, BlockId, BlockPosition, BlockRotation, BlockState, Blocks},
+    database::Database,
+    networking::{NetworkMessage, Server},
+    physics::Velocity,
+    prelude::*,
+    protocol::messages,
+    utils,
+    world::{BlockUpdate, ChangedBlockEvent, WorldMap},
+};
+use serde::{Deserialize, Serialize};
+
+pub(super) struct FluidsPlugin;
+impl Plugin for FluidsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(SaveTimer(Timer::from_seconds(60.0, TimerMode::Repeating)))
+            .add_systems(Startup, setup)
+            .add_systems(
+                Update,
+                (
+                    (
+                        start_spreading,
+                        unload_fluid_updates,
+                        spread_fluids,
+                        resume_stored_updates,
+                        save_fluid_updates,
+                    )
+                        .chain(),
+                    push_entities,
+                    push_players,
+                ),
+            )
+            // The final save is done last, so it sees the exit no matter when it was sent
+            // during the frame.
+            .add_systems(
+                Last,
+                save_fluid_updates_on_exit.run_if(on_event::<AppExit>()),
+            );
+    }
+}
+
+/// How a fluid behaves, its blocks are found by the name of the fluid. See 'Fluid::new'.
+struct FluidConfig {
+    name: &'static str,
+    /// How many blocks it can flow away from a source.
+    spread_distance: u8,
+    /// How many ticks it takes to flow one block.
+    delay: u64,
+    /// If a block of flowing fluid between two or more sources becomes a source itself.
+    infinite_sources: bool,
+    /// Fluid that makes this fluid solidify when they touch, and the block it turns into.
+    solidifies: Option<(&'static str, &'static str)>,
+    /// How fast flowing fluid accelerates the entities in it, in blocks per second squared.
+    current: f64,
+}
+
+const FLUIDS: [FluidConfig; 2] = [
+    FluidConfig {
+        name: "water",
+        spread_distance: 8,
+        delay: 4,
+        infinite_sources: true,
+        solidifies: None,
+        current: 12.0,
+    },
+    FluidConfig {
+        name: "lava",
+        spread_distance: 3,
+        delay: 20,
+        infinite_sources: false,
+        solidifies: Some(("water", "stone")),
+        current: 3.0,
+    },
+];
+
+// Fluid updates are scheduled in ticks of this length.
+const TICK: Duration = Duration::from_millis(50);
+// Most updates that are sent in one frame, the rest are delayed until the next. Updates that are
+// overdue are sent first.
+const MAX_UPDATES_PER_FRAME: usize = 2048;
+
+fn setup(mut commands: Commands, database: Res<Database>, mut blocks: ResMut<Blocks>) {
+    let fluids = Fluids::new(|name| blocks.get_id(name));
+
+    for fluid in fluids.fluids.iter() {
+        blocks
+            .get_config_mut(&fluid.spreading)
+            .set_spawn_function(spawn_spreading_fluid);
+    }
+
+    commands.insert_resource(fluids);
+
+    let conn = database.get_connection();
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS fluid_updates (
+            x INTEGER NOT NULL,
+            y INTEGER NOT NULL,
+            z INTEGER NOT NULL,
+            save TEXT NOT NULL
+        )",
+        [],
+    )
+    .unwrap();
+
+    // Updates that were waiting when the server stopped are resumed as their chunks are loaded.
+    let mut stmt = conn.prepare("SELECT save FROM fluid_updates").unwrap();
+    let mut stored = StoredFluidUpdates::default();
+    for save in stmt.query_map([], |row| row.get::<_, String>(0)).unwrap() {
+        let save: FluidUpdateSave = serde_json::from_str(&save.unwrap()).unwrap();
+        let chunk_position = utils::world_position_to_chunk_position(save.position);
+        stored.entry(chunk_position).or_default().push(save);
+    }
+    commands.insert_resource(stored);
+}
+
+#[derive(Resource)]
+struct Fluids {
+    fluids: Vec<Fluid>,
+    // For each fluid, the index of the fluid that makes it solidify and the block it becomes.
+    solidifies: Vec<Option<(usize, BlockId)>>,
+    // The current tick, and the time since it started.
+    tick: u64,
+    elapsed: Duration,
+}
+
+impl Fluids {
+    // 'get_id' gives the id of the block with the given name.
+    fn new(get_id: impl Fn(&str) -> BlockId) -> Self {
+        let fluids = FLUIDS
+            .iter()
+            .map(|config| Fluid::new(config, &get_id))
+            .collect();
+
+        let solidifies = FLUIDS
+            .iter()
+            .map(|config| {
+                config.solidifies.map(|(fluid_name, block_name)| {
+                    let Some(fluid) = FLUIDS.iter().position(|other| other.name == fluid_name)
+                    else {
+                        panic!(
+                            "The fluid '{}' solidifies when it touches '{}', but there is no \
+                            fluid by that name.",
+                            config.name, fluid_name
+                        );
+                    };
+                    (fluid, get_id(block_name))
+                })
+            })
+            .collect();
+
+        Self {
+            fluids,
+            solidifies,
+            tick: 0,
+            elapsed: Duration::ZERO,
+        }
+    }
+
+    fn get_fluid(&self, block: &(BlockId, Option<BlockState>)) -> Option<usize> {
+        self.fluids
+            .iter()
+            .position(|fluid| fluid.block_to_fluid.contains_key(block))
+    }
+
+    // When two fluids that solidify meet, the block of the one that solidifies is replaced.
+    // Returns the position that should be replaced, and the block to replace it with.
+    fn solidify(&self, changed_block: &ChangedBlockEvent) -> Option<(IVec3, BlockId)> {
+        let to = self.get_fluid(&changed_block.to)?;
+
+        for block_face in [
+            BlockFace::Front,
+            BlockFace::Back,
+            BlockFace::Right,
+            BlockFace::Left,
+            BlockFace::Top,
+            BlockFace::Bottom,
+        ] {
+            let Some(adjacent) = changed_block[block_face]
+                .as_ref()
+                .and_then(|block| self.get_fluid(block))
+            else {
+                continue;
+            };
+
+            if let Some((fluid, block_id)) = self.solidifies[to] {
+                if fluid == adjacent {
+                    return Some((changed_block.position, block_id));
+                }
+            }
+
+            if let Some((fluid, block_id)) = self.solidifies[adjacent] {
+                if fluid == to {
+                    return Some((block_face.shift_position(changed_block.position), block_id));
+                }
+            }
+        }
+
+        None
+    }
+}
+
+struct Fluid {
+    name: &'static str,
+    // The source block that is placed at the surface of the fluid, and its stand-in that makes it
+    // start to flow.
+    surface: BlockId,
+    spreading: BlockId,
+    fluid_to_block: HashMap<FluidBlock, (BlockId, Option<BlockState>)>,
+    block_to_fluid: HashMap<(BlockId, Option<BlockState>), FluidBlock>,
+    // Flowing fluid below this level dries up.
+    min_level: FluidLevel,
+    infinite_sources: bool,
+    current: f64,
+    // Updates waiting until they are due
+    updates: ScheduledUpdates,
+}
+
+// Updates that are due a number of ticks after they are first scheduled. Scheduling a position
+// that is already waiting replaces its update, but it stays due at the same tick, so fluid that
+// keeps changing isn't held back forever.
+struct ScheduledUpdates {
+    // The tick updates are scheduled from
+    now: u64,
+    delay: u64,
+    updates: HashMap<IVec3, (u64, FluidBlock)>,
+    // The positions by when they are due, soonest first. Entries that no longer match the tick
+    // the position is due at are left behind when an update is removed, they are skipped.
+    queue: BinaryHeap<Reverse<(u64, [i32; 3])>>,
+}
+
+impl ScheduledUpdates {
+    fn new(delay: u64) -> Self {
+        Self {
+            now: 0,
+            delay,
+            updates: HashMap::new(),
+            queue: BinaryHeap::new(),
+        }
+    }
+
+    fn get(&self, position: &IVec3) -> Option<&FluidBlock> {
+        self.updates
+            .get(position)
+            .map(|(_, fluid_block)| fluid_block)
+    }
+
+    fn insert(&mut self, position: IVec3, fluid_block: FluidBlock) {
+        *self.get_or_insert(position) = fluid_block;
+    }
+
+    // The update waiting at the position, a new empty one is scheduled if there is none.
+    fn get_or_insert(&mut self, position: IVec3) -> &mut FluidBlock {
+        self.schedule(position, self.now + self.delay, FluidBlock::default())
+    }
+
+    fn schedule(&mut self, position: IVec3, due: u64, fluid_block: FluidBlock) -> &mut FluidBlock {
+        let (_, update) = self.updates.entry(position).or_insert_with(|| {
+            self.queue.push(Reverse((due, position.to_array())));
+            (due, fluid_block)
+        });
+        update
+    }
+
+    // Remove the update waiting at the position, returns when it was due and the update.
+    fn remove(&mut self, position: &IVec3) -> Option<(u64, FluidBlock)> {
+        self.updates.remove(position)
+    }
+
+    // Take the updates that are due by 'tick', at most 'budget' of them.
+    fn take_due(&mut self, tick: u64, budget: usize) -> Vec<(IVec3, FluidBlock)> {
+        let mut due = Vec::new();
+        while due.len() < budget {
+            let Some(Reverse((due_tick, position))) = self.queue.peek().copied() else {
+                break;
+            };
+            if due_tick > tick {
+                break;
+            }
+            self.queue.pop();
+
+            let position = IVec3::from_array(position);
+            if self
+                .updates
+                .get(&position)
+                .is_some_and(|(scheduled, _)| *scheduled == due_tick)
+            {
+                let (_, fluid_block) = self.updates.remove(&position).unwrap();
+                due.push((position, fluid_block));
+            }
+        }
+        due
+    }
+}
+
+impl Fluid {
+    // The blocks of a fluid named 'lava' are:
+    //  surface_lava, subsurface_lava - sources
+    //  spreading_lava - turns into surface_lava when loaded, so it starts flowing
+    //  still_lava_10 - flowing fluid with fluid above it
+    //  still_lava_9..1, straight_lava_8..1, diagonal_lava_7..1, diagonal_lava_corner_up_8..1,
+    //  diagonal_lava_corner_down_8..1, tilted_lava_8..1 - flowing fluid at decreasing levels,
+    //  only the levels it can spread to are needed.
+    // 'get_id' gives the id of the block with the given name.
+    fn new(config: &FluidConfig, get_id: impl Fn(&str) -> BlockId) -> Self {
+        let min_level = FluidLevel::from_u8(9 - config.spread_distance);
+
+        let surface = get_id(&format!("surface_{}", config.name));
+        let subsurface = get_id(&format!("subsurface_{}", config.name));
+        let still_ten = get_id(&format!("still_{}_10", config.name));
+
+        let mut fluid = Fluid {
+            name: config.name,
+            surface,
+            spreading: get_id(&format!("spreading_{}", config.name)),
+            fluid_to_block: HashMap::new(),
+            block_to_fluid: HashMap::new(),
+            min_level,
+            infinite_sources: config.infinite_sources,
+            current: config.current,
+            updates: ScheduledUpdates::new(config.delay),
+        };
+
+        // The blocks of a shape, from 'highest' down to the lowest level the fluid reaches.
+        let levels = |shape: &str, highest: u8| -> Vec<BlockId> {
+            let name = shape.replace("{fluid}", config.name);
+            (min_level as u8..=highest)
+                .rev()
+                .map(|level| get_id(&format!("{}_{}", name, level)))
+                .collect()
+        };
+
+        fluid.add(
+            FluidBlock {
+                corners: [
+                    FluidLevel::Nine,
+                    FluidLevel::Nine,
+                    FluidLevel::Nine,
+                    FluidLevel::Nine,
+                ],
+                is_source: false,
+            },
+            levels("still_{fluid}", 9),
+        );
+        fluid.add(
+            FluidBlock {
+                corners: [
+                    FluidLevel::Eight,
+                    FluidLevel::Eight,
+                    FluidLevel::Nine,
+                    FluidLevel::Nine,
+                ],
+                is_source: false,
+            },
+            levels("straight_{fluid}", 8),
+        );
+
+        fluid.add(
+            FluidBlock {
+                corners: [
+                    FluidLevel::Seven,
+                    FluidLevel::Eight,
+                    FluidLevel::Nine,
+                    FluidLevel::Eight,
+                ],
+                is_source: false,
+            },
+            levels("diagonal_{fluid}", 7),
+        );
+
+        fluid.add(
+            FluidBlock {
+                corners: [
+                    FluidLevel::Eight,
+                    FluidLevel::Eight,
+                    FluidLevel::Nine,
+                    FluidLevel::Eight,
+                ],
+                is_source: false,
+            },
+            levels("diagonal_{fluid}_corner_up", 8),
+        );
+
+        fluid.add(
+            FluidBlock {
+                corners: [
+                    FluidLevel::Eight,
+                    FluidLevel::Nine,
+                    FluidLevel::Nine,
+                    FluidLevel::Nine,
+                ],
+                is_source: false,
+            },
+            levels("diagonal_{fluid}_corner_down", 8),
+        );
+
+        fluid.add(
+            FluidBlock {
+                corners: [
+                    FluidLevel::Nine,
+                    FluidLevel::Eight,
+                    FluidLevel::Nine,
+                    FluidLevel::Eight,
+                ],
+                is_source: false,
+            },
+            levels("tilted_{fluid}", 8),
+        );
+
+        // Have to add the two source block types manually, as well as still_{fluid}_10 because it
+        // works the same way as subsurface.
+        fluid.fluid_to_block.insert(
+            FluidBlock {
+                corners: [
+                    FluidLevel::Nine,
+                    FluidLevel::Nine,
+                    FluidLevel::Nine,
+                    FluidLevel::Nine,
+                ],
+                is_source: true,
+            },
+            (surface, None),
+        );
+        fluid.block_to_fluid.insert(
+            (surface, None),
+            FluidBlock {
+                corners: [
+                    FluidLevel::Nine,
+                    FluidLevel::Nine,
+                    FluidLevel::Nine,
+                    FluidLevel::Nine,
+                ],
+                is_source: true,
+            },
+        );
+
+        fluid.fluid_to_block.insert(
+            FluidBlock {
+                corners: [
+                    FluidLevel::Ten,
+                    FluidLevel::Ten,
+                    FluidLevel::Ten,
+                    FluidLevel::Ten,
+                ],
+                is_source: true,
+            },
+            (subsurface, None),
+        );
+        // This is one level lower to mimic surface water, since nothing can spread from level 10
+        fluid.block_to_fluid.insert(
+            (subsurface, None),
+            FluidBlock {
+                corners: [
+                    FluidLevel::Ten,
+                    FluidLevel::Ten,
+                    FluidLevel::Ten,
+                    FluidLevel::Ten,
+                ],
+                is_source: true,
+            },
+        );
+
+        fluid.fluid_to_block.insert(
+            FluidBlock {
+                corners: [
+                    FluidLevel::Ten,
+                    FluidLevel::Ten,
+                    FluidLevel::Ten,
+                    FluidLevel::Ten,
+                ],
+                is_source: false,
+            },
+            (still_ten, None),
+        );
+        fluid.block_to_fluid.insert(
+            (still_ten, None),
+            FluidBlock {
+                corners: [
+                    FluidLevel::Ten,
+                    FluidLevel::Ten,
+                    FluidLevel::Ten,
+                    FluidLevel::Ten,
+                ],
+                is_source: false,
+            },
+        );
+
+        // This is for removal of the fluid
+        fluid.fluid_to_block.insert(
+            FluidBlock {
+                corners: [
+                    FluidLevel::Zero,
+                    FluidLevel::Zero,
+                    FluidLevel::Zero,
+                    FluidLevel::Zero,
+                ],
+                is_source: false,
+            },
+            (get_id("air"), None),
+        );
+
+        fluid
+    }
+
+    #[track_caller]
+    fn add(&mut self, mut fluid_block: FluidBlock, block_ids: Vec<BlockId>) {
+        for block_id in block_ids {
+            self.fluid_to_block
+                .insert(fluid_block.clone(), (block_id, None));
+            self.block_to_fluid
+                .insert((block_id, None), fluid_block.clone());
+
+            if fluid_block[Corner::Left] == fluid_block[Corner::Right]
+                && fluid_block[Corner::Left] == fluid_block[Corner::FarRight]
+                && fluid_block[Corner::Left] == fluid_block[Corner::FarLeft]
+            {
+                fluid_block[Corner::Left] = fluid_block[Corner::Left].decrement();
+                fluid_block[Corner::Right] = fluid_block[Corner::Right].decrement();
+                fluid_block[Corner::FarRight] = fluid_block[Corner::FarRight].decrement();
+                fluid_block[Corner::FarLeft] = fluid_block[Corner::FarLeft].decrement();
+
+                continue;
+            }
+
+            for i in 1..4 {
+                let rotation = BlockRotation::from(i);
+                self.fluid_to_block.insert(
+                    fluid_block.rotate(rotation),
+                    (block_id, Some(BlockState::new(rotation))),
+                );
+                self.block_to_fluid.insert(
+                    (block_id, Some(BlockState::new(rotation))),
+                    fluid_block.rotate(rotation),
+                );
+            }
+
+            fluid_block[Corner::Left] = fluid_block[Corner::Left].decrement();
+            fluid_block[Corner::Right] = fluid_block[Corner::Right].decrement();
+            fluid_block[Corner::FarRight] = fluid_block[Corner::FarRight].decrement();
+            fluid_block[Corner::FarLeft] = fluid_block[Corner::FarLeft].decrement();
+        }
+    }
+}
+
+#[derive(
+    Default, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Debug, Serialize, Deserialize,
+)]
+enum FluidLevel {
+    #[default]
+    Zero,
+    One,
+    Two,
+    Three,
+    Four,
+    Five,
+    Six,
+    Seven,
+    Eight,
+    Nine,
+    Ten,
+}
+
+impl FluidLevel {
+    #[track_caller]
+    fn from_u8(level: u8) -> Self {
+        match level {
+            0 => Self::Zero,
+            1 => Self::One,
+            2 => Self::Two,
+            3 => Self::Three,
+            4 => Self::Four,
+            5 => Self::Five,
+            6 => Self::Six,
+            7 => Self::Seven,
+            8 => Self::Eight,
+            9 => Self::Nine,
+            10 => Self::Ten,
+            _ => unreachable!(),
+        }
+    }
+
+    #[track_caller]
+    fn decrement(self) -> Self {
+        match self {
+            Self::Zero => unreachable!(),
+            Self::One => Self::Zero,
+            Self::Two => Self::One,
+            Self::Three => Self::Two,
+            Self::Four => Self::Three,
+            Self::Five => Self::Four,
+            Self::Six => Self::Five,
+            Self::Seven => Self::Six,
+            Self::Eight => Self::Seven,
+            Self::Nine => Self::Eight,
+            Self::Ten => Self::Nine,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+enum Corner {
+    Left = 0,
+    Right,
+    FarRight,
+    FarLeft,
+}
+
+impl Corner {
+    fn rotate(&self, rotation: BlockRotation) -> Self {
+        let new = (*self as usize + rotation as usize) % 4;
+        match new {
+            0 => Corner::Left,
+            1 => Corner::Right,
+            2 => Corner::FarRight,
+            3 => Corner::FarLeft,
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[derive(Default, PartialEq, Eq, Hash, Clone, Debug, Serialize, Deserialize)]
+struct FluidBlock {
+    corners: [FluidLevel; 4],
+    is_source: bool,
+}
+
+impl FluidBlock {
+    fn rotate(&self, rotation: BlockRotation) -> Self {
+        match rotation {
+            BlockRotation::Once => FluidBlock {
+                corners: [
+                    self.corners[Corner::FarLeft as usize],
+                    self.corners[Corner::Left as usize],
+                    self.corners[Corner::Right as usize],
+                    self.corners[Corner::FarRight as usize],
+                ],
+                is_source: self.is_source,
+            },
+            BlockRotation::Twice => FluidBlock {
+                corners: [
+                    self.corners[Corner::FarRight as usize],
+                    self.corners[Corner::FarLeft as usize],
+                    self.corners[Corner::Left as usize],
+                    self.corners[Corner::Right as usize],
+                ],
+                is_source: self.is_source,
+            },
+            BlockRotation::Thrice => FluidBlock {
+                corners: [
+                    self.corners[Corner::Right as usize],
+                    self.corners[Corner::FarRight as usize],
+                    self.corners[Corner::FarLeft as usize],
+                    self.corners[Corner::Left as usize],
+                ],
+                is_source: self.is_source,
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    fn update_corner(&mut self, corner: Corner, water_level: FluidLevel) {
+        if self.corners[corner as usize] == water_level
+            || self.corners[corner as usize] == FluidLevel::Ten
+        {
+            return;
+        }
+
+        self.corners[corner as usize] = water_level;
+
+        if self[corner.rotate(BlockRotation::Once)] < self[corner] {
+            self[corner.rotate(BlockRotation::Once)] = self[corner].decrement();
+        }
+
+        if self[corner.rotate(BlockRotation::Thrice)] < self[corner] {
+            self[corner.rotate(BlockRotation::Thrice)] = self[corner].decrement();
+        }
+
+        if self[corner.rotate(BlockRotation::Twice)] < self[corner.rotate(BlockRotation::Once)]
+            || self[corner.rotate(BlockRotation::Twice)]
+                < self[corner.rotate(BlockRotation::Thrice)]
+        {
+            self[corner.rotate(BlockRotation::Twice)] = self[corner.rotate(BlockRotation::Once)]
+                .max(self[corner.rotate(BlockRotation::Thrice)])
+                .decrement();
+        }
+    }
+}
+
+impl FluidBlock {
+    // Horizontal direction the fluid flows in, towards its lower corners. Zero if it is level.
+    fn flow_direction(&self) -> DVec3 {
+        let face = |block_face: BlockFace| block_face.shift_position(IVec3::ZERO).as_dvec3();
+        let mut direction = DVec3::ZERO;
+        for (corner, corner_direction) in [
+            (Corner::Left, face(BlockFace::Left) + face(BlockFace::Front)),
+            (
+                Corner::Right,
+                face(BlockFace::Right) + face(BlockFace::Front),
+            ),
+            (
+                Corner::FarRight,
+                face(BlockFace::Right) + face(BlockFace::Back),
+            ),
+            (
+                Corner::FarLeft,
+                face(BlockFace::Left) + face(BlockFace::Back),
+            ),
+        ] {
+            direction -= corner_direction * self[corner] as u8 as f64;
+        }
+        direction.normalize_or_zero()
+    }
+}
+
+impl Index<Corner> for FluidBlock {
+    type Output = FluidLevel;
+    fn index(&self, index: Corner) -> &Self::Output {
+        &self.corners[index as usize]
+    }
+}
+
+impl IndexMut<Corner> for FluidBlock {
+    fn index_mut(&mut self, index: Corner) -> &mut Self::Output {
+        &mut self.corners[index as usize]
+    }
+}
+
+const TEN: FluidBlock = FluidBlock {
+    corners: [
+        FluidLevel::Ten,
+        FluidLevel::Ten,
+        FluidLevel::Ten,
+        FluidLevel::Ten,
+    ],
+    is_source: false,
+};
+
+#[derive(Debug)]
+struct ChangedBlockAsFluid {
+    pub to: Option<FluidBlock>,
+    pub top: Option<FluidBlock>,
+    pub bottom: Option<FluidBlock>,
+    pub back: Option<FluidBlock>,
+    pub back_right: Option<FluidBlock>,
+    pub back_left: Option<FluidBlock>,
+    pub right: Option<FluidBlock>,
+    pub left: Option<FluidBlock>,
+    pub front: Option<FluidBlock>,
+    pub front_right: Option<FluidBlock>,
+    pub front_left: Option<FluidBlock>,
+}
+
+impl ChangedBlockAsFluid {
+    fn new(changed_block: &ChangedBlockEvent, fluid: &Fluid) -> Self {
+        Self {
+            to: fluid.block_to_fluid.get(&changed_block.to).cloned(),
+            top: changed_block
+                .top
+                .as_ref()
+                .and_then(|top| fluid.block_to_fluid.get(top).cloned()),
+            bottom: changed_block
+                .bottom
+                .as_ref()
+                .and_then(|bottom| fluid.block_to_fluid.get(bottom).cloned()),
+            back: changed_block
+                .back
+                .as_ref()
+                .and_then(|back| fluid.block_to_fluid.get(back).cloned()),
+            back_right: changed_block
+                .back_right
+                .as_ref()
+                .and_then(|back_right| fluid.block_to_fluid.get(back_right).cloned()),
+            back_left: changed_block
+                .back_left
+                .as_ref()
+                .and_then(|back_left| fluid.block_to_fluid.get(back_left).cloned()),
+            right: changed_block
+                .right
+                .as_ref()
+                .and_then(|right| fluid.block_to_fluid.get(right).cloned()),
+            left: changed_block
+                .left
+                .as_ref()
+                .and_then(|left| fluid.block_to_fluid.get(left).cloned()),
+            front: changed_block
+                .front
+                .as_ref()
+                .and_then(|front| fluid.block_to_fluid.get(front).cloned()),
+            front_right: changed_block
+                .front_right
+                .as_ref()
+                .and_then(|front_right| fluid.block_to_fluid.get(front_right).cloned()),
+            front_left: changed_block
+                .front_left
+                .as_ref()
+                .and_then(|front_left| fluid.block_to_fluid.get(front_left).cloned()),
+        }
+    }
+}
+
+impl Index<BlockFace> for ChangedBlockAsFluid {
+    type Output = Option<FluidBlock>;
+    fn index(&self, index: BlockFace) -> &Self::Output {
+        match index {
+            BlockFace::Front => &self.front,
+            BlockFace::Back => &self.back,
+            BlockFace::Right => &self.right,
+            BlockFace::Left => &self.left,
+            BlockFace::Top => &self.top,
+            BlockFace::Bottom => &self.bottom,
+        }
+    }
+}
+
+impl Index<[BlockFace; 2]> for ChangedBlockAsFluid {
+    type Output = Option<FluidBlock>;
+    #[track_caller]
+    fn index(&self, index: [BlockFace; 2]) -> &Self::Output {
+        match index {
+            [BlockFace::Front, BlockFace::Left] => &self.front_left,
+            [BlockFace::Left, BlockFace::Front] => &self.front_left,
+            [BlockFace::Front, BlockFace::Right] => &self.front_right,
+            [BlockFace::Right, BlockFace::Front] => &self.front_right,
+            [BlockFace::Back, BlockFace::Left] => &self.back_left,
+            [BlockFace::Left, BlockFace::Back] => &self.back_left,
+            [BlockFace::Back, BlockFace::Right] => &self.back_right,
+            [BlockFace::Right, BlockFace::Back] => &self.back_right,
+            _ => panic!("Tried to index with non-horizontal blockfaces."),
+        }
+    }
+}
+
+// Marks the stand-in blocks the terrain generation places where fluid should flow.
+#[derive(Component)]
+struct SpreadingFluid;
+
+fn spawn_spreading_fluid(commands: &mut EntityCommands, _block_data: Option<&BlockData>) {
+    commands.insert(SpreadingFluid);
+}
+
+// Fluid in a freshly generated chunk doesn't know it should flow, so the terrain generation uses
+// a stand-in for it where it can, e.g. at a cave opening. When the stand-in is loaded it is
+// replaced by the real fluid, which then spreads as if it had just been placed.
+fn start_spreading(
+    fluids: Res<Fluids>,
+    world_map: Res<WorldMap>,
+    spreading: Query<&BlockPosition, Added<SpreadingFluid>>,
+    mut block_updates: EventWriter<BlockUpdate>,
+) {
+    for block_position in spreading.iter() {
+        let Some(block_id) = world_map.get_block(**block_position) else {
+            continue;
+        };
+        let Some(fluid) = fluids
+            .fluids
+            .iter()
+            .find(|fluid| fluid.spreading == block_id)
+        else {
+            continue;
+        };
+
+        block_updates.send(BlockUpdate::Change {
+            position: **block_position,
+            block_id: fluid.surface,
+            block_state: None,
+        });
+    }
+}
+
+fn spread_fluids(
+    time: Res<Time>,
+    mut fluids: ResMut<Fluids>,
+    mut changed_blocks: EventReader<ChangedBlockEvent>,
+    mut block_updates: EventWriter<BlockUpdate>,
+) {
+    let air = Blocks::get().get_id("air");
+
+    let solidified = fluids.react(changed_blocks.read(), air);
+    block_updates.send_batch(solidified);
+
+    fluids.elapsed += time.delta();
+    while fluids.elapsed >= TICK {
+        fluids.elapsed -= TICK;
+        fluids.tick += 1;
+    }
+
+    let updates = fluids.take_updates(MAX_UPDATES_PER_FRAME, air);
+    block_updates.send_batch(updates);
+}
+
+// Updates that are stored with the chunk they are in, until the chunk is loaded again.
+#[derive(Resource, Default, Deref, DerefMut)]
+struct StoredFluidUpdates(HashMap<IVec3, Vec<FluidUpdateSave>>);
+
+#[derive(Serialize, Deserialize, Clone)]
+struct FluidUpdateSave {
+    fluid: String,
+    position: IVec3,
+    // How many ticks there were left until it was due
+    remaining: u64,
+    update: FluidBlock,
+}
+
+#[derive(Resource, Deref, DerefMut)]
+struct SaveTimer(Timer);
+
+fn resume_stored_updates(
+    world_map: Res<WorldMap>,
+    mut fluids: ResMut<Fluids>,
+    mut stored: ResMut<StoredFluidUpdates>,
+) {
+    if stored.is_empty() {
+        return;
+    }
+
+    let loaded: Vec<IVec3> = stored
+        .keys()
+        .filter(|chunk_position| world_map.contains_chunk(chunk_position))
+        .copied()
+        .collect();
+
+    let tick = fluids.tick;
+    for chunk_position in loaded {
+        for save in stored.remove(&chunk_position).unwrap() {
+            // Fluids that no longer exist are dropped.
+            let Some(fluid) = fluids
+                .fluids
+                .iter_mut()
+                .find(|fluid| fluid.name == save.fluid)
+            else {
+                continue;
+            };
+            fluid
+                .updates
+                .schedule(save.position, tick + save.remaining, save.update);
+        }
+    }
+}
+
+// Updates in chunks that are no longer loaded are stored until the chunk is loaded again.
+fn unload_fluid_updates(
+    world_map: Res<WorldMap>,
+    mut fluids: ResMut<Fluids>,
+    mut stored: ResMut<StoredFluidUpdates>,
+) {
+    let tick = fluids.tick;
+    for fluid in fluids.fluids.iter_mut() {
+        let unloaded: Vec<(IVec3, IVec3)> = fluid
+            .updates
+            .updates
+            .keys()
+            .map(|position| {
+                (
+                    *position,
+                    utils::world_position_to_chunk_position(*position),
+                )
+            })
+            .filter(|(_, chunk_position)| !world_map.contains_chunk(chunk_position))
+            .collect();
+
+        for (position, chunk_position) in unloaded {
+            let (due, update) = fluid.updates.remove(&position).unwrap();
+            stored
+                .entry(chunk_position)
+                .or_default()
+                .push(FluidUpdateSave {
+                    fluid: fluid.name.to_owned(),
+                    position,
+                    remaining: due.saturating_sub(tick),
+                    update,
+                });
+        }
+    }
+}
+
+// The waiting updates are saved periodically so floods continue after a restart.
+fn save_fluid_updates(
+    database: Res<Database>,
+    time: Res<Time>,
+    fluids: Res<Fluids>,
+    stored: Res<StoredFluidUpdates>,
+    mut save_timer: ResMut<SaveTimer>,
+) {
+    if save_timer.tick(time.delta()).just_finished() {
+        store_fluid_updates(&database, &fluids, &stored);
+    }
+}
+
+fn save_fluid_updates_on_exit(
+    database: Res<Database>,
+    fluids: Res<Fluids>,
+    stored: Res<StoredFluidUpdates>,
+) {
+    store_fluid_updates(&database, &fluids, &stored);
+}
+
+// All rows are replaced by the updates that are waiting, and those stored for chunks that
+// aren't loaded.
+fn store_fluid_updates(database: &Database, fluids: &Fluids, stored: &StoredFluidUpdates) {
+    let mut conn = database.get_connection();
+    let transaction = conn.transaction().unwrap();
+    transaction
+        .execute("DELETE FROM fluid_updates", [])
+        .unwrap();
+
+    {
+        let mut insert = transaction
+            .prepare("INSERT INTO fluid_updates (x, y, z, save) VALUES (?, ?, ?, ?)")
+            .unwrap();
+        let waiting = fluids.fluids.iter().flat_map(|fluid| {
+            fluid
+                .updates
+                .updates
+                .iter()
+                .map(|(position, (due, update))| FluidUpdateSave {
+                    fluid: fluid.name.to_owned(),
+                    position: *position,
+                    remaining: due.saturating_sub(fluids.tick),
+                    update: update.clone(),
+                })
+        });
+        for save in waiting.chain(stored.values().flatten().cloned()) {
+            insert
+                .execute(rusqlite::params![
+                    save.position.x,
+                    save.position.y,
+                    save.position.z,
+                    serde_json::to_string(&save).unwrap()
+                ])
+                .unwrap();
+        }
+    }
+
+    transaction.commit().unwrap();
+}
+
+impl Fluids {
+    // Let the fluids react to the blocks that changed, what changes is scheduled from the current
+    // tick. Returns the blocks that solidified where two fluids met.
+    fn react<'a>(
+        &mut self,
+        changed_blocks: impl IntoIterator<Item = &'a ChangedBlockEvent>,
+        air: BlockId,
+    ) -> Vec<BlockUpdate> {
+        for fluid in self.fluids.iter_mut() {
+            fluid.updates.now = self.tick;
+        }
+
+        let mut solidified = Vec::new();
+
+        for changed_block in changed_blocks {
+            // If there's an update waiting to be sent, but the block is changed, the update is stale
+            for fluid in self.fluids.iter_mut() {
+                fluid.updates.remove(&changed_block.position);
+            }
+
+            if let Some((position, block_id)) = self.solidify(changed_block) {
+                // Fluid waiting to flow into the solidified block would replace it.
+                for fluid in self.fluids.iter_mut() {
+                    fluid.updates.remove(&position);
+                }
+                solidified.push(BlockUpdate::Change {
+                    position,
+                    block_id,
+                    block_state: None,
+                });
+                // The changed block is what solidified, it will no longer spread.
+                if position == changed_block.position {
+                    continue;
+                }
+            }
+
+            for fluid in self.fluids.iter_mut() {
+                fluid.spread(changed_block, air);
+            }
+        }
+
+        solidified
+    }
+
+    // The updates that are due by the current tick, at most 'budget' of them. Each fluid gets an
+    // equal share of the budget, so one that floods can't hold the others back. What a fluid
+    // doesn't use of its share is left to the others.
+    fn take_updates(&mut self, mut budget: usize, air: BlockId) -> Vec<BlockUpdate> {
+        let mut updates = Vec::new();
+
+        let fluid_count = self.fluids.len();
+        for (index, fluid) in self.fluids.iter_mut().enumerate() {
+            let share = budget / (fluid_count - index);
+            let taken = fluid.take_updates(self.tick, share, air);
+            budget -= taken.len();
+            updates.extend(taken);
+        }
+
+        // The fluids before one that didn't use all of its share may still have updates.
+        for fluid in self.fluids.iter_mut() {
+            if budget == 0 {
+                break;
+            }
+            let taken = fluid.take_updates(self.tick, budget, air);
+            budget -= taken.len();
+            updates.extend(taken);
+        }
+
+        updates
+    }
+
+    // How fast the fluid at the position carries what is in it along, in blocks per second.
+    fn current_at(&self, world_map: &WorldMap, position: DVec3) -> Option<DVec3> {
+        let block_position = position.floor().as_ivec3();
+        let block = (
+            world_map.get_block(block_position)?,
+            world_map.get_block_state(block_position),
+        );
+
+        let fluid = self
+            .fluids
+            .iter()
+            .find(|fluid| fluid.block_to_fluid.contains_key(&block))?;
+
+        let fluid_block = &fluid.block_to_fluid[&block];
+        if fluid_block.is_source {
+            return None;
+        }
+
+        Some(fluid_block.flow_direction() * fluid.current)
+    }
+}
+
+// Flowing fluid carries the entities in it along.
+fn push_entities(
+    time: Res<Time>,
+    fluids: Res<Fluids>,
+    world_map: Res<WorldMap>,
+    mut entities: Query<(&GlobalTransform, &mut Velocity)>,
+) {
+    for (transform, mut velocity) in entities.iter_mut() {
+        if let Some(current) = fluids.current_at(&world_map, transform.translation()) {
+            velocity.0 += current * time.delta_seconds_f64();
+        }
+    }
+}
+
+// Players move on their own, the push is added to the velocity they report and sent back to
+// them.
+fn push_players(
+    net: Res<Server>,
+    time: Res<Time>,
+    fluids: Res<Fluids>,
+    world_map: Res<WorldMap>,
+    mut position_events: EventReader<NetworkMessage<messages::PlayerPosition>>,
+) {
+    for position_update in position_events.read() {
+        let Some(current) = fluids.current_at(&world_map, position_update.position) else {
+            continue;
+        };
+
+        net.send_one(
+            position_update.player_entity,
+            messages::PlayerPosition {
+                position: position_update.position,
+                velocity: position_update.velocity + current * time.delta_seconds_f64(),
+            },
+        );
+    }
+}
+
+impl Fluid {
+    // Find how the fluid should change around the changed block, the changes are stored until the
+    // next tick.
+    fn spread(&mut self, changed_block: &ChangedBlockEvent, air: BlockId) {
+        let change_as_fluid = ChangedBlockAsFluid::new(changed_block, self);
+
+        let mut fluid_block = if let Some(to) = &change_as_fluid.to {
+            if to.is_source {
+                to.clone()
+            } else {
+                let new_max = to.corners.iter().max().unwrap().decrement();
+                FluidBlock {
+                    corners: [
+                        to[Corner::Left].min(new_max),
+                        to[Corner::Right].min(new_max),
+                        to[Corner::FarRight].min(new_max),
+                        to[Corner::FarLeft].min(new_max),
+                    ],
+                    is_source: false,
+                }
+            }
+        } else {
+            FluidBlock::default()
+        };
+
+        if change_as_fluid.top.is_some() {
+            fluid_block.corners = [
+                FluidLevel::Nine,
+                FluidLevel::Nine,
+                FluidLevel::Nine,
+                FluidLevel::Nine,
+            ]
+        } else if !fluid_block.is_source {
+            for (corner, block_faces) in [
+                (Corner::Left, [BlockFace::Left, BlockFace::Front]),
+                (Corner::Left, [BlockFace::Front, BlockFace::Left]),
+                (Corner::Right, [BlockFace::Right, BlockFace::Front]),
+                (Corner::Right, [BlockFace::Front, BlockFace::Right]),
+                (Corner::FarRight, [BlockFace::Right, BlockFace::Back]),
+                (Corner::FarRight, [BlockFace::Back, BlockFace::Right]),
+                (Corner::FarLeft, [BlockFace::Left, BlockFace::Back]),
+                (Corner::FarLeft, [BlockFace::Back, BlockFace::Left]),
+            ] {
+                if let Some(adjacent_fluid_block) = &change_as_fluid[block_faces[0]] {
+                    let (corner_one, corner_two) = match (corner, block_faces[0]) {
+                        (Corner::Left, BlockFace::Left) => (Corner::Right, Corner::Left),
+                        (Corner::Left, BlockFace::Front) => (Corner::FarLeft, Corner::Left),
+                        (Corner::Right, BlockFace::Right) => (Corner::Left, Corner::Right),
+                        (Corner::Right, BlockFace::Front) => (Corner::FarRight, Corner::Right),
+                        (Corner::FarRight, BlockFace::Right) => (Corner::FarLeft, Corner::FarRight),
+                        (Corner::FarRight, BlockFace::Back) => (Corner::Right, Corner::FarRight),
+                        (Corner::FarLeft, BlockFace::Left) => (Corner::FarRight, Corner::FarLeft),
+                        (Corner::FarLeft, BlockFace::Back) => (Corner::Left, Corner::FarLeft),
+                        _ => unreachable!(),
+                    };
+                    if adjacent_fluid_block.is_source {
+                        fluid_block.update_corner(corner, FluidLevel::Nine);
+                    } else {
+                        fluid_block.update_corner(
+                            corner,
+                            fluid_block[corner]
+                                .max(adjacent_fluid_block[corner_one].decrement())
+                                .max(adjacent_fluid_block[corner_two].decrement()),
+                        );
+                    }
+                }
+
+                if change_as_fluid[block_faces[0]].is_some()
+                    && change_as_fluid[block_faces].is_some()
+                {
+                    let diagonal_fluid_block = change_as_fluid[block_faces].as_ref().unwrap();
+                    let (corner_near, corner_far) = match block_faces {
+                        // left corner
+                        [BlockFace::Left, BlockFace::Front] => (Corner::FarRight, Corner::Right),
+                        [BlockFace::Front, BlockFace::Left] => (Corner::FarLeft, Corner::FarRight),
+                        // right corner
+                        [BlockFace::Right, BlockFace::Front] => (Corner::FarLeft, Corner::Left),
+                        [BlockFace::Front, BlockFace::Right] => (Corner::FarLeft, Corner::FarRight),
+                        // far right corner
+                        [BlockFace::Right, BlockFace::Back] => (Corner::FarLeft, Corner::Left),
+                        [BlockFace::Back, BlockFace::Right] => (Corner::Left, Corner::Right),
+                        // far left corner
+                        [BlockFace::Left, BlockFace::Back] => (Corner::FarRight, Corner::Right),
+                        [BlockFace::Back, BlockFace::Left] => (Corner::Left, Corner::Right),
+                        _ => unreachable!(),
+                    };
+                    if diagonal_fluid_block.is_source {
+                        fluid_block.update_corner(corner, FluidLevel::Nine);
+                    } else {
+                        fluid_block.update_corner(
+                            corner,
+                            fluid_block[corner]
+                                .max(diagonal_fluid_block[corner_near].decrement())
+                                .max(diagonal_fluid_block[corner_far].decrement()),
+                        );
+                    }
+                }
+            }
+        }
+
+        if self.infinite_sources
+            && !fluid_block.is_source
+            && fluid_block != FluidBlock::default()
+            && self.is_between_sources(changed_block, &change_as_fluid, air)
+        {
+            fluid_block = FluidBlock {
+                corners: [FluidLevel::Nine; 4],
+                is_source: true,
+            };
+        }
+
+        // Fluid that would be lower than it can spread dries up.
+        if fluid_block != FluidBlock::default()
+            && fluid_block.corners.iter().any(|c| c < &self.min_level)
+        {
+            fluid_block.corners = FluidBlock::default().corners;
+        }
+
+        if fluid_block == FluidBlock::default() && change_as_fluid.to.is_none() {
+            return;
+        }
+
+        for block_face in [
+            BlockFace::Front,
+            BlockFace::Right,
+            BlockFace::Back,
+            BlockFace::Left,
+        ] {
+            //let (orthogonal_one, orthogonal_two) = match block_face {
+            //    BlockFace::Front | BlockFace::Back => (BlockFace::Right, BlockFace::Left),
+            //    BlockFace::Left | BlockFace::Right => (BlockFace::Front, BlockFace::Back),
+            //    _ => unreachable!(),
+            //};
+            let to_corners = match block_face {
+                BlockFace::Left => [Corner::Right, Corner::FarRight],
+                BlockFace::Right => [Corner::Left, Corner::FarLeft],
+                BlockFace::Front => [Corner::FarLeft, Corner::FarRight],
+                BlockFace::Back => [Corner::Left, Corner::Right],
+                _ => unreachable!(),
+            };
+            let from_corners = match block_face {
+                BlockFace::Left => [Corner::Left, Corner::FarLeft],
+                BlockFace::Right => [Corner::Right, Corner::FarRight],
+                BlockFace::Front => [Corner::Left, Corner::Right],
+                BlockFace::Back => [Corner::FarLeft, Corner::FarRight],
+                _ => unreachable!(),
+            };
+            let position = block_face.shift_position(changed_block.position);
+
+            if let Some(adjacent_fluid_block) = &change_as_fluid[block_face] {
+                //if change_as_fluid.to.is_some()
+                //    || (change_as_fluid[orthogonal_one].is_some()
+                //        && change_as_fluid[[orthogonal_one, block_face]].is_some())
+                //    || (change_as_fluid[orthogonal_two].is_some()
+                //        && change_as_fluid[[orthogonal_two, block_face]].is_some())
+                //{
+                let mut update = self
+                    .updates
+                    .get(&position)
+                    .unwrap_or(adjacent_fluid_block)
+                    .clone();
+                update.update_corner(to_corners[0], fluid_block[from_corners[0]]);
+                update.update_corner(to_corners[1], fluid_block[from_corners[1]]);
+
+                // Flowing fluid next to a new source might now be between two sources. It is
+                // updated even if it doesn't change, so it gets to check.
+                let between_sources = self.infinite_sources
+                    && fluid_block.is_source
+                    && !adjacent_fluid_block.is_source;
+
+                if &update != adjacent_fluid_block || between_sources {
+                    self.updates.insert(position, update);
+                }
+                //}
+            } else if changed_block[block_face].is_some_and(|b| b.0 == air)
+                && changed_block.bottom.is_some_and(|b| b.0 != air)
+                && change_as_fluid.bottom.is_none()
+                && fluid_block[from_corners[0]] > self.min_level
+                && fluid_block[from_corners[1]] > self.min_level
+            {
+                let update = self.updates.get_or_insert(position);
+                if update != &TEN {
+                    update.update_corner(to_corners[0], fluid_block[from_corners[0]]);
+                    update.update_corner(to_corners[1], fluid_block[from_corners[1]]);
+                }
+            }
+        }
+
+        for (block_faces, corner_to, corner_from) in [
+            (
+                [BlockFace::Front, BlockFace::Left],
+                Corner::FarRight,
+                Corner::Left,
+            ),
+            (
+                [BlockFace::Front, BlockFace::Right],
+                Corner::FarLeft,
+                Corner::Right,
+            ),
+            (
+                [BlockFace::Back, BlockFace::Right],
+                Corner::Left,
+                Corner::FarRight,
+            ),
+            (
+                [BlockFace::Back, BlockFace::Left],
+                Corner::Right,
+                Corner::FarLeft,
+            ),
+        ] {
+            if let Some(diagonal_fluid_block) = &change_as_fluid[block_faces] {
+                if change_as_fluid[block_faces[0]].is_some()
+                    || change_as_fluid[block_faces[1]].is_some()
+                {
+                    let position = block_faces[0]
+                        .shift_position(block_faces[1].shift_position(changed_block.position));
+                    let mut update = self
+                        .updates
+                        .get(&position)
+                        .unwrap_or(diagonal_fluid_block)
+                        .clone();
+                    update.update_corner(corner_to, fluid_block[corner_from]);
+                    if &update != diagonal_fluid_block {
+                        self.updates.insert(position, update);
+                    }
+                }
+            }
+        }
+
+        if let Some(bottom) = &change_as_fluid.bottom {
+            if fluid_block == FluidBlock::default() {
+                self.updates.insert(
+                    changed_block.position - IVec3::Y,
+                    FluidBlock {
+                        corners: [
+                            FluidLevel::Nine,
+                            FluidLevel::Nine,
+                            FluidLevel::Nine,
+                            FluidLevel::Nine,
+                        ],
+                        is_source: bottom.is_source,
+                    },
+                );
+            }
+        }
+
+        if changed_block.bottom.is_some_and(|block| block.0 == air) {
+            self.updates
+                .insert(changed_block.position - IVec3::Y, TEN.clone());
+        } else if let Some(bottom) = change_as_fluid.bottom {
+            if bottom.corners != TEN.corners {
+                let mut new = TEN.clone();
+                new.is_source = bottom.is_source;
+                self.updates.insert(changed_block.position - IVec3::Y, new);
+            }
+        }
+
+        if changed_block.to.0 == air
+            || change_as_fluid
+                .to
+                .is_some_and(|to| to != fluid_block && to.corners != TEN.corners)
+        {
+            if change_as_fluid.top.is_some() {
+                self.updates.insert(changed_block.position, TEN.clone());
+            } else {
+                self.updates.insert(changed_block.position, fluid_block);
+            }
+        }
+    }
+
+    // If there are at least two sources next to the changed block, and it is held up by the block
+    // below it.
+    fn is_between_sources(
+        &self,
+        changed_block: &ChangedBlockEvent,
+        change_as_fluid: &ChangedBlockAsFluid,
+        air: BlockId,
+    ) -> bool {
+        let sources = [
+            BlockFace::Front,
+            BlockFace::Back,
+            BlockFace::Right,
+            BlockFace::Left,
+        ]
+        .into_iter()
+        .filter(|block_face| {
+            change_as_fluid[*block_face]
+                .as_ref()
+                .is_some_and(|fluid_block| fluid_block.is_source)
+        })
+        .count();
+
+        let is_supported = changed_block.bottom.is_some_and(|bottom| bottom.0 != air)
+            && !change_as_fluid
+                .bottom
+                .as_ref()
+                .is_some_and(|bottom| !bottom.is_source);
+
+        sources >= 2 && is_supported
+    }
+
+    // The updates that are due by 'tick', as block changes. At most 'budget' are taken.
+    fn take_updates(&mut self, tick: u64, budget: usize, air: BlockId) -> Vec<BlockUpdate> {
+        self.updates
+            .take_due(tick, budget)
+            .into_iter()
+            .map(|(position, fluid_block)| {
+                // Updates should always be a state of the fluid, see the test
+                // 'removed_source_at_an_edge_leaves_no_invalid_states'.
+                let (block_id, block_state) = match self.fluid_to_block.get(&fluid_block) {
+                    Some(k) => k.clone(),
+                    None => (air, None),
+                };
+                BlockUpdate::Change {
+                    position,
+                    block_id,
+                    block_state,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+
+    const WATER: &FluidConfig = &FLUIDS[0];
+    const LAVA: &FluidConfig = &FLUIDS[1];
+
+    // Stand-in for the world map that runs the fluids. Positions that haven't been set are air,
+    // except below y=0 where there is a floor of stone.
+    struct TestWorld {
+        block_ids: HashMap<String, BlockId>,
+        blocks: HashMap<IVec3, (BlockId, Option<BlockState>)>,
+        fluids: Fluids,
+        // Blocks that solidified, they are changed with the next updates like 'spread_fluids'
+        // sends them.
+        solidified: Vec<BlockUpdate>,
+    }
+
+    impl TestWorld {
+        fn new() -> Self {
+            let block_ids = RefCell::new(HashMap::from([
+                ("air".to_owned(), 0),
+                ("stone".to_owned(), 1),
+            ]));
+            let fluids = Fluids::new(|name| {
+                let mut block_ids = block_ids.borrow_mut();
+                let next_id = block_ids.len() as BlockId;
+                *block_ids.entry(name.to_owned()).or_insert(next_id)
+            });
+
+            Self {
+                block_ids: block_ids.into_inner(),
+                blocks: HashMap::new(),
+                fluids,
+                solidified: Vec::new(),
+            }
+        }
+
+        fn id(&self, name: &str) -> BlockId {
+            self.block_ids[name]
+        }
+
+        fn get(&self, position: IVec3) -> (BlockId, Option<BlockState>) {
+            match self.blocks.get(&position) {
+                Some(block) => *block,
+                None if position.y < 0 => (self.id("stone"), None),
+                None => (self.id("air"), None),
+            }
+        }
+
+        fn fluid_at(&self, position: IVec3) -> Option<FluidBlock> {
+            let block = self.get(position);
+            self.fluids
+                .fluids
+                .iter()
+                .find_map(|fluid| fluid.block_to_fluid.get(&block))
+                .cloned()
+        }
+
+        // Name of the fluid at the position
+        fn fluid_name_at(&self, position: IVec3) -> Option<&'static str> {
+            let block = self.get(position);
+            self.fluids
+                .get_fluid(&block)
+                .map(|index| self.fluids.fluids[index].name)
+        }
+
+        fn place(&mut self, position: IVec3, name: &str) {
+            self.apply(vec![BlockUpdate::Change {
+                position,
+                block_id: self.id(name),
+                block_state: None,
+            }]);
+        }
+
+        fn changed_block(
+            &self,
+            position: IVec3,
+            from: (BlockId, Option<BlockState>),
+        ) -> ChangedBlockEvent {
+            let face = |block_face: BlockFace| block_face.shift_position(position);
+            let diagonal = |one: BlockFace, two: BlockFace| {
+                Some(self.get(one.shift_position(two.shift_position(position))))
+            };
+            ChangedBlockEvent {
+                position,
+                from,
+                to: self.get(position),
+                top: Some(self.get(face(BlockFace::Top))),
+                bottom: Some(self.get(face(BlockFace::Bottom))),
+                back: Some(self.get(face(BlockFace::Back))),
+                back_right: diagonal(BlockFace::Back, BlockFace::Right),
+                back_left: diagonal(BlockFace::Back, BlockFace::Left),
+                right: Some(self.get(face(BlockFace::Right))),
+                left: Some(self.get(face(BlockFace::Left))),
+                front: Some(self.get(face(BlockFace::Front))),
+                front_right: diagonal(BlockFace::Front, BlockFace::Right),
+                front_left: diagonal(BlockFace::Front, BlockFace::Left),
+            }
+        }
+
+        // Change the blocks, and let the fluids react to the ones that changed like the world
+        // map would have them.
+        fn apply(&mut self, updates: Vec<BlockUpdate>) {
+            let mut changed = Vec::new();
+            for update in updates {
+                let BlockUpdate::Change {
+                    position,
+                    block_id,
+                    block_state,
+                } = update
+                else {
+                    unreachable!("Fluids only change blocks");
+                };
+
+                let from = self.get(position);
+                // There is no event for blocks that are replaced by the same block.
+                if from == (block_id, block_state) {
+                    continue;
+                }
+                self.blocks.insert(position, (block_id, block_state));
+                changed.push((position, from));
+            }
+
+            let changed_blocks: Vec<ChangedBlockEvent> = changed
+                .into_iter()
+                .map(|(position, from)| self.changed_block(position, from))
+                .collect();
+            let air = self.id("air");
+            let solidified = self.fluids.react(&changed_blocks, air);
+            self.solidified.extend(solidified);
+        }
+
+        // Move on by one tick, and apply the updates that are due.
+        fn tick(&mut self) {
+            let air = self.id("air");
+            self.fluids.tick += 1;
+
+            // Anything the fluid can't be turned into a block of would be replaced by air.
+            for fluid in self.fluids.fluids.iter() {
+                for (due, fluid_block) in fluid.updates.updates.values() {
+                    assert!(
+                        *due > self.fluids.tick || fluid.fluid_to_block.contains_key(fluid_block),
+                        "the {} update {:?} has no block",
+                        fluid.name,
+                        fluid_block
+                    );
+                }
+            }
+
+            let mut updates = std::mem::take(&mut self.solidified);
+            updates.extend(self.fluids.take_updates(usize::MAX, air));
+            self.apply(updates);
+        }
+
+        fn run(&mut self, ticks: u64) {
+            for _ in 0..ticks {
+                self.tick();
+            }
+        }
+
+        fn highest_corner(&self, position: IVec3) -> FluidLevel {
+            self.fluid_at(position)
+                .map(|fluid_block| *fluid_block.corners.iter().max().unwrap())
+                .unwrap_or_default()
+        }
+    }
+
+    const ROTATIONS: [BlockRotation; 3] = [
+        BlockRotation::Once,
+        BlockRotation::Twice,
+        BlockRotation::Thrice,
+    ];
+
+    const CORNERS: [Corner; 4] = [
+        Corner::Left,
+        Corner::Right,
+        Corner::FarRight,
+        Corner::FarLeft,
+    ];
+
+    #[test]
+    fn corner_rotates_clockwise() {
+        assert_eq!(Corner::Left.rotate(BlockRotation::Once), Corner::Right);
+        assert_eq!(Corner::Right.rotate(BlockRotation::Once), Corner::FarRight);
+        assert_eq!(
+            Corner::FarRight.rotate(BlockRotation::Once),
+            Corner::FarLeft
+        );
+        assert_eq!(Corner::FarLeft.rotate(BlockRotation::Once), Corner::Left);
+        assert_eq!(Corner::Left.rotate(BlockRotation::Twice), Corner::FarRight);
+        assert_eq!(Corner::Left.rotate(BlockRotation::Thrice), Corner::FarLeft);
+
+        for corner in CORNERS {
+            let there_and_back = corner
+                .rotate(BlockRotation::Once)
+                .rotate(BlockRotation::Thrice);
+            assert_eq!(there_and_back, corner);
+        }
+    }
+
+    #[test]
+    fn fluid_block_rotates_with_its_corners() {
+        let fluid_block = FluidBlock {
+            corners: [
+                FluidLevel::One,
+                FluidLevel::Two,
+                FluidLevel::Three,
+                FluidLevel::Four,
+            ],
+            is_source: false,
+        };
+
+        assert_eq!(
+            fluid_block.rotate(BlockRotation::Once).corners,
+            [
+                FluidLevel::Four,
+                FluidLevel::One,
+                FluidLevel::Two,
+                FluidLevel::Three
+            ]
+        );
+
+        // Each corner's level moves to where the corner itself rotates to.
+        for rotation in ROTATIONS {
+            let rotated = fluid_block.rotate(rotation);
+            for corner in CORNERS {
+                assert_eq!(rotated[corner.rotate(rotation)], fluid_block[corner]);
+            }
+        }
+
+        assert_eq!(
+            fluid_block
+                .rotate(BlockRotation::Once)
+                .rotate(BlockRotation::Once),
+            fluid_block.rotate(BlockRotation::Twice)
+        );
+        assert_eq!(
+            fluid_block
+                .rotate(BlockRotation::Twice)
+                .rotate(BlockRotation::Twice),
+            fluid_block
+        );
+    }
+
+    #[test]
+    fn source_spreads_and_stays() {
+        let mut world = TestWorld::new();
+        world.place(IVec3::ZERO, "surface_water");
+        world.run(30 * WATER.delay);
+
+        assert!(world.fluid_at(IVec3::ZERO).unwrap().is_source);
+        for direction in [IVec3::X, IVec3::NEG_X, IVec3::Z, IVec3::NEG_Z] {
+            let next = world.fluid_at(direction).unwrap();
+            assert!(!next.is_source);
+            // It gets lower the further it flows
+            assert!(world.highest_corner(direction * 2) < world.highest_corner(direction));
+            assert!(world.fluid_at(direction * 12).is_none());
+        }
+    }
+
+    #[test]
+    fn fluid_falls_down() {
+        let mut world = TestWorld::new();
+        world.place(IVec3::new(0, 3, 0), "surface_water");
+        world.run(10 * WATER.delay);
+
+        assert!(world.fluid_at(IVec3::new(0, 2, 0)).is_some());
+        assert!(world.fluid_at(IVec3::ZERO).is_some());
+        assert!(world.fluid_at(IVec3::new(0, -1, 0)).is_none());
+    }
+
+    #[test]
+    fn removed_source_dries_up() {
+        let mut world = TestWorld::new();
+        world.place(IVec3::ZERO, "surface_water");
+        world.run(30 * WATER.delay);
+        assert!(world.fluid_at(IVec3::new(3, 0, 0)).is_some());
+
+        world.place(IVec3::ZERO, "air");
+        world.run(60 * WATER.delay);
+
+        let remaining: Vec<IVec3> = world
+            .blocks
+            .keys()
+            .copied()
+            .filter(|position| world.fluid_at(*position).is_some())
+            .collect();
+        assert!(remaining.is_empty(), "fluid left behind at {:?}", remaining);
+    }
+
+    // Removing the water at edges used to end up with states like [Zero, Zero, One, One] that
+    // have no block, 'TestWorld::tick' fails on any update like that.
+    #[test]
+    fn removed_source_at_an_edge_leaves_no_invalid_states() {
+        let mut world = TestWorld::new();
+        // A pit beside the source, so the water flows over its edge.
+        let air = world.id("air");
+        for x in 2..6 {
+            for z in -4..=4 {
+                world.blocks.insert(IVec3::new(x, -1, z), (air, None));
+            }
+        }
+        world.place(IVec3::ZERO, "surface_water");
+        world.run(30 * WATER.delay);
+        assert!(world.fluid_at(IVec3::new(2, -1, 0)).is_some());
+
+        world.place(IVec3::ZERO, "air");
+        world.run(60 * WATER.delay);
+    }
+
+    #[test]
+    fn water_between_two_sources_becomes_a_source() {
+        let mut world = TestWorld::new();
+        world.place(IVec3::NEG_X, "surface_water");
+        world.place(IVec3::X, "surface_water");
+        world.run(10 * WATER.delay);
+
+        assert!(world.fluid_at(IVec3::ZERO).unwrap().is_source);
+        // Only one source next to it
+        assert!(!world.fluid_at(IVec3::new(2, 0, 0)).unwrap().is_source);
+    }
+
+    #[test]
+    fn water_between_sources_over_air_does_not_become_a_source() {
+        let mut world = TestWorld::new();
+        world
+            .blocks
+            .insert(IVec3::new(0, -1, 0), (world.id("air"), None));
+        world
+            .blocks
+            .insert(IVec3::new(0, -2, 0), (world.id("air"), None));
+        world.place(IVec3::NEG_X, "surface_water");
+        world.place(IVec3::X, "surface_water");
+        world.run(10 * WATER.delay);
+
+        assert!(world
+            .fluid_at(IVec3::ZERO)
+            .is_some_and(|fluid_block| !fluid_block.is_source));
+    }
+
+    #[test]
+    fn finite_fluid_between_sources_keeps_flowing() {
+        let mut world = TestWorld::new();
+        world.place(IVec3::NEG_X, "surface_lava");
+        world.place(IVec3::X, "surface_lava");
+        world.run(10 * LAVA.delay);
+
+        assert!(!world.fluid_at(IVec3::ZERO).unwrap().is_source);
+    }
+
+    #[test]
+    fn lava_that_meets_water_turns_to_stone() {
+        let mut world = TestWorld::new();
+        world.place(IVec3::ZERO, "surface_water");
+        world.place(IVec3::new(3, 0, 0), "surface_lava");
+        world.run(10 * LAVA.delay);
+
+        assert_eq!(world.get(IVec3::new(3, 0, 0)), (world.id("stone"), None));
+        let lava: Vec<IVec3> = world
+            .blocks
+            .keys()
+            .copied()
+            .filter(|position| world.fluid_name_at(*position) == Some("lava"))
+            .collect();
+        assert!(lava.is_empty(), "lava left behind at {:?}", lava);
+        assert_eq!(world.fluid_name_at(IVec3::X), Some("water"));
+    }
+
+    #[test]
+    fn flowing_fluid_pushes_towards_its_lower_corners() {
+        let mut world = TestWorld::new();
+        world.place(IVec3::ZERO, "surface_water");
+        world.run(30 * WATER.delay);
+
+        let direction = world
+            .fluid_at(IVec3::new(2, 0, 0))
+            .unwrap()
+            .flow_direction();
+        assert!(direction.x > 0.5, "{}", direction);
+        assert!(world.fluid_at(IVec3::ZERO).unwrap().flow_direction() == DVec3::ZERO);
+    }
+
+    fn level(level: u8) -> FluidBlock {
+        FluidBlock {
+            corners: [FluidLevel::from_u8(level); 4],
+            is_source: false,
+        }
+    }
+
+    #[test]
+    fn budget_is_shared_between_fluids() {
+        let mut world = TestWorld::new();
+        for x in 0..10 {
+            world.fluids.fluids[0]
+                .updates
+                .insert(IVec3::new(x, 0, 0), level(1));
+        }
+        world.fluids.fluids[1].updates.insert(IVec3::Z, level(1));
+        world.fluids.tick = 100;
+
+        let air = world.id("air");
+        let positions: Vec<IVec3> = world
+            .fluids
+            .take_updates(4, air)
+            .into_iter()
+            .map(|update| match update {
+                BlockUpdate::Change { position, .. } => position,
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(positions.len(), 4);
+        // The water is over its share, but it gets what the lava doesn't use.
+        assert!(positions.contains(&IVec3::Z));
+    }
+
+    #[test]
+    fn updates_are_taken_when_due() {
+        let mut updates = ScheduledUpdates::new(4);
+        updates.insert(IVec3::X, level(1));
+        updates.now = 2;
+        updates.insert(IVec3::Y, level(2));
+
+        assert!(updates.take_due(3, usize::MAX).is_empty());
+        assert_eq!(updates.take_due(4, usize::MAX), vec![(IVec3::X, level(1))]);
+        assert_eq!(updates.take_due(6, usize::MAX), vec![(IVec3::Y, level(2))]);
+        assert!(updates.take_due(100, usize::MAX).is_empty());
+    }
+
+    #[test]
+    fn rescheduling_replaces_the_update_but_not_when_it_is_due() {
+        let mut updates = ScheduledUpdates::new(4);
+        updates.insert(IVec3::X, level(1));
+        updates.now = 3;
+        updates.insert(IVec3::X, level(2));
+
+        assert_eq!(updates.take_due(4, usize::MAX), vec![(IVec3::X, level(2))]);
+        assert!(updates.take_due(7, usize::MAX).is_empty());
+    }
+
+    #[test]
+    fn removed_updates_are_not_taken() {
+        let mut updates = ScheduledUpdates::new(4);
+        updates.insert(IVec3::X, level(1));
+        updates.remove(&IVec3::X);
+        updates.now = 2;
+        updates.insert(IVec3::X, level(2));
+
+        // Only the second one, at the tick it was scheduled for.
+        assert!(updates.take_due(4, usize::MAX).is_empty());
+        assert_eq!(updates.take_due(6, usize::MAX), vec![(IVec3::X, level(2))]);
+    }
+
+    #[test]
+    fn overdue_updates_wait_for_the_budget() {
+        let mut updates = ScheduledUpdates::new(1);
+        for x in 0..5 {
+            updates.now = x as u64;
+            updates.insert(IVec3::new(x, 0, 0), level(1));
+        }
+
+        let first: Vec<IVec3> = updates
+            .take_due(10, 3)
+            .into_iter()
+            .map(|(position, _)| position)
+            .collect();
+        assert_eq!(first, vec![IVec3::ZERO, IVec3::X, IVec3::new(2, 0, 0)]);
+        assert_eq!(updates.take_due(10, 3).len(), 2);
+    }
+}
@@ -0,0 +1,63 @@
+use fmc::{
+    blocks::{BlockPosition, Blocks},
+    prelude::*,
+    world::{BlockUpdate, WorldMap},
+};
+
+use super::{torch::Torch, BrokenBlockReplacements};
+
+// How far from a torch ice melts.
+const MELT_RADIUS: i32 = 2;
+
+pub(super) struct IcePlugin;
+impl Plugin for IcePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(MeltTimer(Timer::from_seconds(5.0, TimerMode::Repeating)))
+            .add_systems(Startup, setup)
+            .add_systems(Update, melt_ice);
+    }
+}
+
+#[derive(Resource, Deref, DerefMut)]
+struct MeltTimer(Timer);
+
+fn setup(blocks: Res<Blocks>, mut replacements: ResMut<BrokenBlockReplacements>) {
+    replacements.insert(blocks.get_id("ice"), blocks.get_id("surface_water"));
+}
+
+// Ice close to a light source melts back into water.
+fn melt_ice(
+    time: Res<Time>,
+    world_map: Res<WorldMap>,
+    mut timer: ResMut<MeltTimer>,
+    torches: Query<&BlockPosition, With<Torch>>,
+    mut block_update_writer: EventWriter<BlockUpdate>,
+) {
+    timer.tick(time.delta());
+    if !timer.just_finished() {
+        return;
+    }
+
+    let blocks = Blocks::get();
+    let ice = blocks.get_id("ice");
+    let water = blocks.get_id("surface_water");
+
+    for block_position in torches.iter() {
+        for x in -MELT_RADIUS..=MELT_RADIUS {
+            for y in -MELT_RADIUS..=MELT_RADIUS {
+                for z in -MELT_RADIUS..=MELT_RADIUS {
+                    let position = **block_position + IVec3::new(x, y, z);
+                    if world_map.get_block(position) != Some(ice) {
+                        continue;
+                    }
+
+                    block_update_writer.send(BlockUpdate::Change {
+                        position,
+                        block_id: water,
+                        block_state: None,
+                    });
+                }
+            }
+        }
+    }
+}
@@ -1,14 +1,28 @@
-use fmc::prelude::*;
+use std::collections::HashMap;
+
+use fmc::{blocks::BlockId, prelude::*};
 
 mod crafting_table;
-mod water;
+mod fluids;
+mod ice;
+mod torch;
+mod vegetation;
 mod wheat;
 
 pub(super) struct BlocksPlugin;
 impl Plugin for BlocksPlugin {
     fn build(&self, app: &mut App) {
-        app.add_plugins(crafting_table::CraftingTablePlugin)
+        app.init_resource::<BrokenBlockReplacements>()
+            .add_plugins(crafting_table::CraftingTablePlugin)
+            .add_plugins(ice::IcePlugin)
+            .add_plugins(torch::TorchPlugin)
+            .add_plugins(vegetation::VegetationPlugin)
             .add_plugins(wheat::WheatPlugin)
-            .add_plugins(water::WaterPlugin);
+            .add_plugins(fluids::FluidsPlugin);
     }
 }
+
+/// Blocks that leave another block in their place when broken, like ice that melts into water.
+/// All other blocks leave air.
+#[derive(Resource, Default, Deref, DerefMut)]
+pub struct BrokenBlockReplacements(HashMap<BlockId, BlockId>);
@@ -0,0 +1,97 @@
+use fmc::{
+    bevy::ecs::system::EntityCommands,
+    blocks::{BlockData, BlockPosition, Blocks},
+    items::Items,
+    models::Models,
+    prelude::*,
+    world::{BlockUpdate, WorldMap},
+};
+
+use crate::{items::GroundItemBundle, weather::Weather};
+
+pub struct TorchPlugin;
+impl Plugin for TorchPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ExtinguishTimer(Timer::from_seconds(
+            5.0,
+            TimerMode::Repeating,
+        )))
+        .add_systems(Startup, setup)
+        .add_systems(Update, extinguish_torches);
+    }
+}
+
+#[derive(Component)]
+pub(super) struct Torch;
+
+#[derive(Resource, Deref, DerefMut)]
+struct ExtinguishTimer(Timer);
+
+fn setup(mut blocks: ResMut<Blocks>) {
+    let block_id = blocks.get_id("torch");
+    let block = blocks.get_config_mut(&block_id);
+    block.set_spawn_function(spawn_torch);
+}
+
+fn spawn_torch(commands: &mut EntityCommands, _block_data: Option<&BlockData>) {
+    commands.insert(Torch);
+}
+
+// Torches that are out in the rain burn out, leaving only the stick.
+fn extinguish_torches(
+    mut commands: Commands,
+    time: Res<Time>,
+    weather: Res<Weather>,
+    world_map: Res<WorldMap>,
+    items: Res<Items>,
+    models: Res<Models>,
+    mut timer: ResMut<ExtinguishTimer>,
+    torches: Query<&BlockPosition, With<Torch>>,
+    mut block_update_writer: EventWriter<BlockUpdate>,
+) {
+    timer.tick(time.delta());
+    if !timer.just_finished() {
+        return;
+    }
+
+    let blocks = Blocks::get();
+    let air = blocks.get_id("air");
+
+    for block_position in torches.iter() {
+        if !weather.is_raining_at(**block_position) {
+            continue;
+        }
+
+        // Exposed if there's only air between the torch and the sky. Chunks that aren't loaded
+        // can't shelter it.
+        let mut position = **block_position + IVec3::Y;
+        let mut sheltered = false;
+        while let Some(block_id) = world_map.get_block(position) {
+            if block_id != air {
+                sheltered = true;
+                break;
+            }
+            position.y += 1;
+        }
+
+        if sheltered {
+            continue;
+        }
+
+        block_update_writer.send(BlockUpdate::Change {
+            position: **block_position,
+            block_id: air,
+            block_state: None,
+        });
+
+        let item_id = items.get_id("stick").unwrap();
+        let item_config = items.get_config(&item_id);
+        commands.spawn(GroundItemBundle::new(
+            item_id,
+            item_config,
+            models.get_by_id(item_config.model_id),
+            1,
+            block_position.as_dvec3(),
+        ));
+    }
+}
@@ -0,0 +1,21 @@
+use fmc::{blocks::Blocks, items::Items, prelude::*};
+
+use crate::items::{RandomDrop, RandomDrops};
+
+pub struct VegetationPlugin;
+impl Plugin for VegetationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Startup, setup);
+    }
+}
+
+fn setup(blocks: Res<Blocks>, items: Res<Items>, mut random_drops: ResMut<RandomDrops>) {
+    // Seeds can be found in the grass, so farming can start before a hoe is made.
+    random_drops.insert(
+        blocks.get_id("tall_grass"),
+        RandomDrop {
+            item_id: items.get_id("wheat_seeds").unwrap(),
+            chance: 0.125,
+        },
+    );
+}
@@ -5,10 +5,17 @@ use fmc::{
     world::BlockUpdate,
 };
 
+use crate::weather::Weather;
+
 pub struct WheatPlugin;
 impl Plugin for WheatPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(Startup, setup).add_systems(Update, grow);
+        app.insert_resource(RainCheckTimer(Timer::from_seconds(
+            5.0,
+            TimerMode::Repeating,
+        )))
+        .add_systems(Startup, setup)
+        .add_systems(Update, grow);
     }
 }
 
@@ -16,8 +23,25 @@ impl Plugin for WheatPlugin {
 struct Wheat {
     stage: u8,
     tick: u8,
+    // If it is raining on it, None until it has been checked.
+    raining: Option<bool>,
+}
+
+impl Wheat {
+    fn new(stage: u8) -> Self {
+        Self {
+            stage,
+            tick: 0,
+            raining: None,
+        }
+    }
 }
 
+// Looking up the weather at a position is too slow to do for every wheat every tick, it is
+// checked on an interval and when the weather changes.
+#[derive(Resource, Deref, DerefMut)]
+struct RainCheckTimer(Timer);
+
 fn setup(mut blocks: ResMut<Blocks>) {
     let block_id = blocks.get_id("wheat_0");
     let block = blocks.get_config_mut(&block_id);
@@ -49,36 +73,47 @@ fn setup(mut blocks: ResMut<Blocks>) {
 }
 
 fn spawn_wheat_0(commands: &mut EntityCommands, _block_data: Option<&BlockData>) {
-    commands.insert(Wheat { stage: 0, tick: 0 });
+    commands.insert(Wheat::new(0));
 }
 fn spawn_wheat_1(commands: &mut EntityCommands, _block_data: Option<&BlockData>) {
-    commands.insert(Wheat { stage: 1, tick: 0 });
+    commands.insert(Wheat::new(1));
 }
 fn spawn_wheat_2(commands: &mut EntityCommands, _block_data: Option<&BlockData>) {
-    commands.insert(Wheat { stage: 2, tick: 0 });
+    commands.insert(Wheat::new(2));
 }
 fn spawn_wheat_3(commands: &mut EntityCommands, _block_data: Option<&BlockData>) {
-    commands.insert(Wheat { stage: 3, tick: 0 });
+    commands.insert(Wheat::new(3));
 }
 fn spawn_wheat_4(commands: &mut EntityCommands, _block_data: Option<&BlockData>) {
-    commands.insert(Wheat { stage: 4, tick: 0 });
+    commands.insert(Wheat::new(4));
 }
 fn spawn_wheat_5(commands: &mut EntityCommands, _block_data: Option<&BlockData>) {
-    commands.insert(Wheat { stage: 5, tick: 0 });
+    commands.insert(Wheat::new(5));
 }
 fn spawn_wheat_6(commands: &mut EntityCommands, _block_data: Option<&BlockData>) {
-    commands.insert(Wheat { stage: 6, tick: 0 });
+    commands.insert(Wheat::new(6));
 }
 
 // TODO: Make 'tick' increment randomly.
 // TODO: Only run this function at daytime?
 fn grow(
+    time: Res<Time>,
+    weather: Res<Weather>,
+    mut rain_check_timer: ResMut<RainCheckTimer>,
     mut growing: Query<(&mut Wheat, &BlockPosition)>,
     mut block_update_writer: EventWriter<BlockUpdate>,
 ) {
+    let check_rain = rain_check_timer.tick(time.delta()).just_finished() || weather.is_changed();
+
     for (mut wheat, block_position) in growing.iter_mut() {
-        if !wheat.tick != 30 {
-            wheat.tick += 1;
+        if check_rain || wheat.raining.is_none() {
+            wheat.raining = Some(weather.is_raining_at(**block_position));
+        }
+
+        if wheat.tick < 225 {
+            // Crops grow twice as fast in the rain
+            let growth = if wheat.raining == Some(true) { 2 } else { 1 };
+            wheat.tick = wheat.tick.saturating_add(growth);
             continue;
         }
 
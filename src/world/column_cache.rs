@@ -0,0 +1,112 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use fmc::prelude::*;
+
+/// Values computed for whole chunk columns, keyed by the x and z position of the column. When
+/// full, the least recently used column is evicted. It can be shared between threads.
+pub struct ColumnCache<T> {
+    capacity: usize,
+    entries: Mutex<Entries<T>>,
+}
+
+struct Entries<T> {
+    // The value of each column, and when it was last used.
+    columns: HashMap<IVec2, (Arc<T>, u64)>,
+    clock: u64,
+}
+
+impl<T> ColumnCache<T> {
+    /// A cache that holds at most 'capacity' columns, 0 disables it.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(Entries {
+                columns: HashMap::with_capacity(capacity),
+                clock: 0,
+            }),
+        }
+    }
+
+    /// Get the value of the column, it is computed with 'compute' if it isn't cached. The cache
+    /// isn't locked while computing, so if multiple threads ask for the same column at once, they
+    /// might all compute it.
+    pub fn get_or_insert_with(&self, column: IVec2, compute: impl FnOnce() -> T) -> Arc<T> {
+        {
+            let mut entries = self.entries.lock().unwrap();
+            let entries = &mut *entries;
+            entries.clock += 1;
+            if let Some((value, last_used)) = entries.columns.get_mut(&column) {
+                *last_used = entries.clock;
+                return value.clone();
+            }
+        }
+
+        let value = Arc::new(compute());
+
+        if self.capacity == 0 {
+            return value;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        if entries.columns.len() >= self.capacity && !entries.columns.contains_key(&column) {
+            // The cache is small enough that searching it is cheap compared to computing a column.
+            let least_recently_used = entries
+                .columns
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(column, _)| *column)
+                .unwrap();
+            entries.columns.remove(&least_recently_used);
+        }
+        let clock = entries.clock;
+        entries.columns.insert(column, (value.clone(), clock));
+
+        return value;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn computes_each_column_once() {
+        let cache = ColumnCache::new(4);
+        let mut computed = 0;
+        for _ in 0..3 {
+            let value = cache.get_or_insert_with(IVec2::new(16, -32), || {
+                computed += 1;
+                7
+            });
+            assert_eq!(*value, 7);
+        }
+        assert_eq!(computed, 1);
+    }
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let cache = ColumnCache::new(2);
+        let a = IVec2::new(0, 0);
+        let b = IVec2::new(16, 0);
+        let c = IVec2::new(32, 0);
+
+        cache.get_or_insert_with(a, || 'a');
+        cache.get_or_insert_with(b, || 'b');
+        // Using 'a' makes 'b' the least recently used, so it is the one evicted by 'c'.
+        cache.get_or_insert_with(a, || unreachable!());
+        cache.get_or_insert_with(c, || 'c');
+
+        assert_eq!(*cache.get_or_insert_with(a, || 'x'), 'a');
+        assert_eq!(*cache.get_or_insert_with(b, || 'x'), 'x');
+    }
+
+    #[test]
+    fn zero_capacity_caches_nothing() {
+        let cache = ColumnCache::new(0);
+        cache.get_or_insert_with(IVec2::ZERO, || 1);
+        assert_eq!(*cache.get_or_insert_with(IVec2::ZERO, || 2), 2);
+    }
+}
@@ -0,0 +1,67 @@
+use serde::de::DeserializeOwned;
+
+/// Read every file in the directory as json, named by the file. 'kind' is what the files are
+/// called in the errors, e.g. "biome". They are sorted by name so they are always in the same
+/// order.
+pub fn read_config_files<T: DeserializeOwned>(path: &str, kind: &str) -> Vec<(String, T)> {
+    let directory = match std::fs::read_dir(path) {
+        Ok(d) => d,
+        Err(e) => panic!(
+            "Couldn't read files from the {} directory, make sure it is present at: {}\nError: {}",
+            kind, path, e
+        ),
+    };
+
+    let mut errors = Vec::new();
+    let mut configs = Vec::new();
+
+    for dir_entry in directory {
+        let file_path = match dir_entry {
+            Ok(d) => d.path(),
+            Err(e) => panic!("Failed to read the filename of a {}\nError: {}", kind, e),
+        };
+
+        let Some(name) = file_path.file_stem().and_then(|stem| stem.to_str()) else {
+            errors.push(format!(
+                "Invalid {} file name: {}",
+                kind,
+                file_path.display()
+            ));
+            continue;
+        };
+
+        let file = match std::fs::File::open(&file_path) {
+            Ok(f) => f,
+            Err(e) => {
+                errors.push(format!(
+                    "Failed to open {} at path: {}\nError: {}",
+                    kind,
+                    file_path.display(),
+                    e
+                ));
+                continue;
+            }
+        };
+
+        match serde_json::from_reader::<_, T>(file) {
+            Ok(config) => configs.push((name.to_owned(), config)),
+            Err(e) => errors.push(format!(
+                "Failed to read {} at path: {}\nError: {}",
+                kind,
+                file_path.display(),
+                e
+            )),
+        }
+    }
+
+    if !errors.is_empty() {
+        panic!(
+            "Startup failed while reading the {} files.\n{}",
+            kind,
+            errors.join("\n")
+        );
+    }
+
+    configs.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+    return configs;
+}
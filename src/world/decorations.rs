@@ -0,0 +1,214 @@
+use std::{collections::HashMap, ops::RangeInclusive};
+
+use fmc::{
+    blocks::{BlockId, Blocks, BLOCK_CONFIG_PATH},
+    prelude::*,
+    world::{blueprints::TerrainFeature, chunk::Chunk},
+};
+use rand::Rng;
+use serde::Deserialize;
+
+use super::config_files::read_config_files;
+
+pub const DECORATION_PATH: &str = "resources/server/decorations";
+
+/// Small plants and such scattered over the surface of a biome.
+#[derive(Clone)]
+pub struct Decoration {
+    block: BlockId,
+    // Blocks the decoration can stand on
+    place_on: Vec<BlockId>,
+    // Blocks the decoration can take the place of, e.g. air or water
+    replace: Vec<BlockId>,
+    // Blocks of which at least one must be next to the block it stands on, any if empty.
+    next_to: Vec<BlockId>,
+    count: u32,
+    height: RangeInclusive<u32>,
+}
+
+impl Decoration {
+    /// Scatter the decoration over the block columns that belong to the biome. 'column_biomes'
+    /// is the biome index of each block column, indexed by x << 4 | z. 'liquid' gives the liquid
+    /// the terrain generation filled a block with, by its position relative to the chunk. It
+    /// also knows the blocks bordering the chunk. 'above_chunk' gives the air or liquid the
+    /// terrain generation leaves above the chunk, None where it is solid. The part of a decoration
+    /// that reaches above the chunk is left to it as a terrain feature.
+    pub fn generate(
+        &self,
+        chunk_position: IVec3,
+        chunk: &mut Chunk,
+        column_biomes: &[usize],
+        biome_index: usize,
+        liquid: &impl Fn(IVec3) -> Option<BlockId>,
+        above_chunk: &impl Fn(IVec3) -> Option<BlockId>,
+        rng: &mut impl Rng,
+    ) {
+        let mut terrain_feature = TerrainFeature {
+            blocks: HashMap::new(),
+            can_replace: self.replace.iter().copied().collect(),
+        };
+
+        for _ in 0..self.count {
+            let x = rng.gen_range(0..Chunk::SIZE);
+            let z = rng.gen_range(0..Chunk::SIZE);
+            let height = rng.gen_range(self.height.clone()) as usize;
+
+            if column_biomes[x << 4 | z] != biome_index {
+                continue;
+            }
+
+            let block_at = |chunk: &Chunk, y: usize| {
+                if y < Chunk::SIZE {
+                    Some(chunk[[x, y, z]])
+                } else {
+                    above_chunk(IVec3::new(x as i32, y as i32, z as i32))
+                }
+            };
+            let can_replace = |chunk: &Chunk, y: usize| {
+                block_at(chunk, y).is_some_and(|block| self.replace.contains(&block))
+            };
+
+            // The highest place in the column it can stand.
+            let Some(y) = (0..Chunk::SIZE)
+                .rev()
+                .find(|y| self.place_on.contains(&chunk[[x, *y, z]]) && can_replace(chunk, y + 1))
+            else {
+                continue;
+            };
+
+            if !self.next_to.is_empty() && !self.is_next_to(chunk, liquid, x, y, z) {
+                continue;
+            }
+
+            for y in y + 1..y + 1 + height {
+                if !can_replace(chunk, y) {
+                    break;
+                }
+                if y < Chunk::SIZE {
+                    chunk[[x, y, z]] = self.block;
+                } else {
+                    let position = chunk_position + IVec3::new(x as i32, y as i32, z as i32);
+                    terrain_feature.blocks.insert(position, (self.block, None));
+                }
+            }
+        }
+
+        if !terrain_feature.blocks.is_empty() {
+            chunk.terrain_features.push(terrain_feature);
+        }
+    }
+
+    // The liquid is what the terrain generation made, even if it has since been marked to spread
+    // or something was placed in it. The neighbouring chunks only know that much, so looking at it
+    // the same way inside the chunk gives the same result on both sides of the border.
+    fn is_next_to(
+        &self,
+        chunk: &Chunk,
+        liquid: &impl Fn(IVec3) -> Option<BlockId>,
+        x: usize,
+        y: usize,
+        z: usize,
+    ) -> bool {
+        [IVec3::X, IVec3::NEG_X, IVec3::Z, IVec3::NEG_Z]
+            .into_iter()
+            .filter_map(|offset| {
+                let position = IVec3::new(x as i32, y as i32, z as i32) + offset;
+                let inside_chunk = position.cmpge(IVec3::ZERO).all()
+                    && position.cmplt(IVec3::splat(Chunk::SIZE as i32)).all();
+                liquid(position).or_else(|| {
+                    inside_chunk.then(|| {
+                        chunk[[
+                            position.x as usize,
+                            position.y as usize,
+                            position.z as usize,
+                        ]]
+                    })
+                })
+            })
+            .any(|block| self.next_to.contains(&block))
+    }
+}
+
+#[derive(Deserialize)]
+struct DecorationJson {
+    block: String,
+    place_on: Vec<String>,
+    replace: Vec<String>,
+    #[serde(default)]
+    next_to: Vec<String>,
+    // How many times per chunk it tries to place the decoration
+    count: u32,
+    // Inclusive range of how many blocks tall it is
+    #[serde(default = "default_height")]
+    height: [u32; 2],
+}
+
+fn default_height() -> [u32; 2] {
+    [1, 1]
+}
+
+/// Load the decorations from the decoration directory, keyed by their file name.
+pub fn load_decorations(blocks: &Blocks) -> HashMap<String, Decoration> {
+    let decoration_jsons: Vec<(String, DecorationJson)> =
+        read_config_files(DECORATION_PATH, "decoration");
+
+    let mut errors = Vec::new();
+    let mut decorations = HashMap::new();
+
+    for (name, decoration) in decoration_jsons {
+        let mut valid = true;
+        for block_name in std::iter::once(&decoration.block)
+            .chain(decoration.place_on.iter())
+            .chain(decoration.replace.iter())
+            .chain(decoration.next_to.iter())
+        {
+            if !blocks.contains_block(block_name) {
+                errors.push(format!(
+                    "The decoration '{}' references a block with the name '{}', but no block by \
+                    that name exists. Make sure a block by the same name is present at '{}'",
+                    name, block_name, BLOCK_CONFIG_PATH
+                ));
+                valid = false;
+            }
+        }
+
+        let [min_height, max_height] = decoration.height;
+        if min_height == 0 || min_height > max_height {
+            errors.push(format!(
+                "The decoration '{}' has an invalid height range, the first height must be at \
+                least 1 and no more than the second.",
+                name
+            ));
+            valid = false;
+        }
+
+        if !valid {
+            continue;
+        }
+
+        let get_ids = |names: &[String]| -> Vec<BlockId> {
+            names.iter().map(|name| blocks.get_id(name)).collect()
+        };
+
+        decorations.insert(
+            name,
+            Decoration {
+                block: blocks.get_id(&decoration.block),
+                place_on: get_ids(&decoration.place_on),
+                replace: get_ids(&decoration.replace),
+                next_to: get_ids(&decoration.next_to),
+                count: decoration.count,
+                height: min_height..=max_height,
+            },
+        );
+    }
+
+    if !errors.is_empty() {
+        panic!(
+            "Startup failed while validating the decorations.\n{}",
+            errors.join("\n")
+        );
+    }
+
+    return decorations;
+}
@@ -1,30 +1,121 @@
-use fmc::{blocks::Blocks, database::Database, prelude::*, world::WorldMap};
+use std::sync::Arc;
+
+use fmc::{
+    bevy::{app::AppExit, log::warn},
+    blocks::Blocks,
+    database::Database,
+    prelude::*,
+    world::{TerrainGenerator, WorldMap},
+};
 use serde::{Deserialize, Serialize};
 
+use crate::{settings::Settings, weather::WeatherKind};
+
 mod biomes;
 pub mod blocks;
+mod column_cache;
+mod config_files;
+mod decorations;
+mod ores;
+mod pregeneration;
+mod structures;
 mod terrain_generation;
+mod world_types;
+
+pub use biomes::{Biome, Precipitation};
+pub use pregeneration::PregenerateOnStartup;
+pub use terrain_generation::Earth;
+pub use world_types::{Flat, FlatLayer, Void, WorldType};
 
 pub struct WorldPlugin;
 impl Plugin for WorldPlugin {
     fn build(&self, app: &mut App) {
         app.add_plugins(blocks::BlocksPlugin)
+            .add_plugins(pregeneration::PregenerationPlugin)
             .add_systems(Startup, setup)
             .add_systems(
                 Update,
                 save_world_properties.run_if(resource_changed::<WorldProperties>),
-            );
+            )
+            // Systems that write to the world properties when the server stops run before this
+            // in Last.
+            .add_systems(Last, save_world_properties.run_if(on_event::<AppExit>()));
     }
 }
 
-fn setup(mut commands: Commands, database: Res<Database>, blocks: Res<Blocks>) {
-    let properties = WorldProperties::load(database).unwrap_or(WorldProperties::default());
+fn setup(
+    mut commands: Commands,
+    database: Res<Database>,
+    blocks: Res<Blocks>,
+    settings: Res<Settings>,
+) {
+    let properties = match WorldProperties::load(&database) {
+        // Worlds from before the world type was stored were all normal worlds
+        Some(properties) if properties.world_type.is_none() => WorldProperties {
+            world_type: Some(WorldType::Normal),
+            ..properties
+        },
+        Some(properties) => properties,
+        None => WorldProperties {
+            world_type: Some(settings.world_type.clone()),
+            ..default()
+        },
+    };
+
+    // The world type can't change once the world has been generated, it would generate
+    // mismatching terrain next to the existing chunks.
+    let world_type = properties.world_type.clone().unwrap();
+    if world_type != settings.world_type {
+        warn!(
+            "The world was created as '{}', the world type setting '{}' is ignored. Create a new \
+            world to use it.",
+            world_type, settings.world_type
+        );
+    }
+
+    let generator: Arc<dyn TerrainGenerator> = match world_type {
+        WorldType::Normal => {
+            let earth = Earth::new(0, &blocks);
+            commands.insert_resource(BiomeMap(Some(earth.clone())));
+            Arc::new(earth)
+        }
+        WorldType::Island { radius } => {
+            let earth = Earth::island(0, &blocks, radius);
+            commands.insert_resource(BiomeMap(Some(earth.clone())));
+            Arc::new(earth)
+        }
+        WorldType::Flat { layers } => {
+            commands.insert_resource(BiomeMap(None));
+            Arc::new(Flat::new(&layers, &blocks))
+        }
+        WorldType::Void => {
+            commands.insert_resource(BiomeMap(None));
+            Arc::new(Void::new(&blocks))
+        }
+    };
+
+    commands.insert_resource(pregeneration::TerrainGeneratorSource(generator.clone()));
+    commands.insert_resource(WorldMap::new(pregeneration::StoredTerrain::new(
+        generator,
+        database.clone(),
+    )));
+
+    // Inserting it counts as a change, so the world type is saved right away.
     commands.insert_resource(properties);
+}
 
-    commands.insert_resource(WorldMap::new(terrain_generation::Earth::new(0, &blocks)));
+/// Biome lookup for systems outside of terrain generation. Empty if the terrain generator does
+/// not use biomes.
+#[derive(Resource, Clone)]
+pub struct BiomeMap(Option<Earth>);
+
+impl BiomeMap {
+    pub fn get_biome(&self, x: i32, z: i32) -> Option<&Biome> {
+        self.0.as_ref().map(|earth| earth.get_biome(x, z))
+    }
 }
 
-fn save_world_properties(database: Res<Database>, properties: Res<WorldProperties>) {
+pub fn save_world_properties(database: Res<Database>, properties: Res<WorldProperties>) {
     properties.save(database);
 }
 
@@ -33,11 +124,24 @@ pub struct WorldProperties {
     // TODO: This must be set to a valid spawn point when first inserted, currently it is just
     // ignored.
     pub spawn_point: SpawnPoint,
+    #[serde(default)]
+    pub time: WorldTime,
+    #[serde(default)]
+    pub weather: WorldWeather,
+    /// The terrain the world was created with. None only for worlds created before it was stored.
+    #[serde(default)]
+    pub world_type: Option<WorldType>,
+    /// Pre-generation that hasn't finished yet
+    #[serde(default)]
+    pub pregeneration: Option<pregeneration::PregenerationJob>,
 }
 
 impl WorldProperties {
-    fn load(database: Res<Database>) -> Option<WorldProperties> {
-        let conn = database.get_connection();
+    fn load(database: &Database) -> Option<WorldProperties> {
+        Self::read(&database.get_connection())
+    }
+
+    pub fn read(conn: &rusqlite::Connection) -> Option<WorldProperties> {
         let mut stmt = conn
             .prepare("SELECT data FROM storage WHERE name = ?")
             .unwrap();
@@ -71,3 +175,29 @@ pub struct SpawnPoint {
     pub center: IVec3,
     pub radius: i32,
 }
+
+/// The state of the world clock, see [crate::skybox::Clock].
+#[derive(Serialize, Deserialize, Clone)]
+pub struct WorldTime {
+    /// Seconds since the world was created, not counting the time it was frozen.
+    pub elapsed: f64,
+    pub frozen: bool,
+}
+
+impl Default for WorldTime {
+    fn default() -> Self {
+        Self {
+            // Start a little after the sun has risen so it's brighter.
+            elapsed: 20.0,
+            frozen: false,
+        }
+    }
+}
+
+/// The state of the weather, see [crate::weather::Weather].
+#[derive(Default, Serialize, Deserialize, Clone)]
+pub struct WorldWeather {
+    pub kind: WeatherKind,
+    /// Seconds until the weather changes
+    pub remaining: f32,
+}
@@ -0,0 +1,225 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ops::RangeInclusive,
+};
+
+use fmc::{
+    blocks::{BlockId, Blocks, BLOCK_CONFIG_PATH},
+    prelude::*,
+    world::{blueprints::TerrainFeature, chunk::Chunk},
+};
+use rand::Rng;
+use serde::Deserialize;
+
+use super::{biomes::Biomes, config_files::read_config_files};
+
+pub const ORE_PATH: &str = "resources/server/ores";
+
+/// A kind of ore vein, and where in the world it is generated. Like a blueprint it is
+/// constructed into a terrain feature, so veins can continue into the neighbouring chunks.
+#[derive(Clone)]
+pub struct Ore {
+    ore_block: BlockId,
+    can_replace: HashSet<BlockId>,
+    frequency: f32,
+    min_height: i32,
+    max_height: i32,
+    peak_height: i32,
+    vein_size: RangeInclusive<u32>,
+    // Indices of the biomes the ore is generated in, all biomes if None.
+    biomes: Option<Vec<usize>>,
+}
+
+impl Ore {
+    // How likely the ore is to appear at the height. 1 at the peak height, falling off linearly
+    // to 0 at the edges of the height range.
+    fn height_weight(&self, height: i32) -> f32 {
+        if height < self.min_height || height > self.max_height {
+            0.0
+        } else if height <= self.peak_height {
+            (height - self.min_height + 1) as f32 / (self.peak_height - self.min_height + 1) as f32
+        } else {
+            (self.max_height - height + 1) as f32 / (self.max_height - self.peak_height + 1) as f32
+        }
+    }
+
+    /// Construct the veins of the ore that start in the chunk. 'column_biomes' is the biome
+    /// index of each block column, indexed by x << 4 | z.
+    pub fn construct(
+        &self,
+        chunk_position: IVec3,
+        column_biomes: &[usize],
+        rng: &mut impl Rng,
+    ) -> TerrainFeature {
+        let mut terrain_feature = TerrainFeature {
+            blocks: HashMap::new(),
+            can_replace: self.can_replace.clone(),
+        };
+
+        if chunk_position.y + Chunk::SIZE as i32 <= self.min_height
+            || chunk_position.y > self.max_height
+        {
+            return terrain_feature;
+        }
+
+        // Every attempt is accepted with the same probability so that 'frequency' veins are made
+        // on average at the peak height.
+        let attempts = self.frequency.ceil() as u32;
+        let probability = self.frequency / attempts as f32;
+
+        for _ in 0..attempts {
+            let mut position = IVec3::new(
+                rng.gen_range(0..Chunk::SIZE as i32),
+                rng.gen_range(0..Chunk::SIZE as i32),
+                rng.gen_range(0..Chunk::SIZE as i32),
+            );
+            let vein_size = rng.gen_range(self.vein_size.clone());
+
+            let weight = self.height_weight(chunk_position.y + position.y);
+            if rng.gen::<f32>() >= probability * weight {
+                continue;
+            }
+
+            if let Some(biomes) = &self.biomes {
+                let column_biome = column_biomes[(position.x << 4 | position.z) as usize];
+                if !biomes.contains(&column_biome) {
+                    continue;
+                }
+            }
+
+            // The vein wanders randomly from where it starts, the parts that go outside the
+            // chunk are placed when the chunks they are in are generated.
+            for _ in 0..vein_size {
+                terrain_feature
+                    .blocks
+                    .insert(chunk_position + position, (self.ore_block, None));
+
+                position += match rng.gen_range(0..6) {
+                    0 => IVec3::X,
+                    1 => IVec3::NEG_X,
+                    2 => IVec3::Y,
+                    3 => IVec3::NEG_Y,
+                    4 => IVec3::Z,
+                    _ => IVec3::NEG_Z,
+                };
+            }
+        }
+
+        return terrain_feature;
+    }
+}
+
+#[derive(Deserialize)]
+struct OreJson {
+    ore_block: String,
+    can_replace: Vec<String>,
+    // Average number of veins per chunk at the peak height
+    frequency: f32,
+    // Inclusive range of heights the ore can be generated at
+    height_range: [i32; 2],
+    // The height the ore is most common at, defaults to the middle of the height range.
+    #[serde(default)]
+    peak_height: Option<i32>,
+    // Inclusive range of how many blocks a vein can have
+    vein_size: [u32; 2],
+    // The names of the biomes the ore is generated in. All biomes if left empty.
+    #[serde(default)]
+    biomes: Vec<String>,
+}
+
+/// Load the ores from the ore directory, they are sorted by name so they are always generated in
+/// the same order.
+pub fn load_ores(blocks: &Blocks, biomes: &Biomes) -> Vec<Ore> {
+    let ore_jsons: Vec<(String, OreJson)> = read_config_files(ORE_PATH, "ore");
+
+    let mut errors = Vec::new();
+
+    let mut ores = Vec::with_capacity(ore_jsons.len());
+
+    for (ore_name, ore) in ore_jsons {
+        for block_name in std::iter::once(&ore.ore_block).chain(ore.can_replace.iter()) {
+            if !blocks.contains_block(block_name) {
+                errors.push(format!(
+                    "The ore '{}' references a block with the name '{}', but no block by that \
+                    name exists. Make sure a block by the same name is present at '{}'",
+                    ore_name, block_name, BLOCK_CONFIG_PATH
+                ));
+            }
+        }
+
+        let [min_height, max_height] = ore.height_range;
+        let peak_height = ore.peak_height.unwrap_or((min_height + max_height) / 2);
+        if min_height > max_height {
+            errors.push(format!(
+                "The ore '{}' has an empty height range, the first height must be the lowest.",
+                ore_name
+            ));
+        } else if !(min_height..=max_height).contains(&peak_height) {
+            errors.push(format!(
+                "The peak height of the ore '{}' is outside of its height range.",
+                ore_name
+            ));
+        }
+
+        let [min_vein_size, max_vein_size] = ore.vein_size;
+        if min_vein_size > max_vein_size {
+            errors.push(format!(
+                "The ore '{}' has an empty vein size range, the first size must be the smallest.",
+                ore_name
+            ));
+        }
+
+        if ore.frequency < 0.0 {
+            errors.push(format!(
+                "The frequency of the ore '{}' can't be negative.",
+                ore_name
+            ));
+        }
+
+        let mut biome_indices = Vec::with_capacity(ore.biomes.len());
+        for biome_name in ore.biomes.iter() {
+            match biomes.iter().position(|biome| &biome.name == biome_name) {
+                Some(index) => biome_indices.push(index),
+                None => errors.push(format!(
+                    "The ore '{}' is restricted to a biome with the name '{}', but no biome by \
+                    that name exists. Make sure a biome by the same name is present at '{}'",
+                    ore_name,
+                    biome_name,
+                    super::biomes::BIOME_PATH
+                )),
+            }
+        }
+
+        if !errors.is_empty() {
+            continue;
+        }
+
+        ores.push(Ore {
+            ore_block: blocks.get_id(&ore.ore_block),
+            can_replace: ore
+                .can_replace
+                .iter()
+                .map(|name| blocks.get_id(name))
+                .collect(),
+            frequency: ore.frequency,
+            min_height,
+            max_height,
+            peak_height,
+            vein_size: min_vein_size..=max_vein_size,
+            biomes: if biome_indices.is_empty() {
+                None
+            } else {
+                Some(biome_indices)
+            },
+        });
+    }
+
+    if !errors.is_empty() {
+        panic!(
+            "Startup failed while validating the ores.\n{}",
+            errors.join("\n")
+        );
+    }
+
+    return ores;
+}
@@ -0,0 +1,510 @@
+use std::{collections::HashSet, sync::Arc};
+
+use fmc::{
+    bevy::{
+        log::{error, info},
+        tasks::{AsyncComputeTaskPool, Task},
+    },
+    blocks::{BlockId, BlockState},
+    database::Database,
+    prelude::*,
+    world::{blueprints::TerrainFeature, chunk::Chunk, TerrainGenerator},
+};
+use futures_lite::future;
+use serde::{Deserialize, Serialize};
+
+use crate::{console::ConsoleCommand, settings::Settings};
+
+use super::WorldProperties;
+
+// Chunks are pre-generated between these heights. Above and below them the terrain is mostly
+// uniform, and fast to generate anyway.
+const BOTTOM: i32 = -64;
+const TOP: i32 = 192;
+const CHUNKS_PER_COLUMN: usize = ((TOP - BOTTOM) / Chunk::SIZE as i32) as usize;
+
+/// Generates and stores the chunks around spawn ahead of time, so players don't have to wait
+/// for them when they first get there. It is started with the 'pregenerate' console command or
+/// the '--pregenerate' argument, and continues where it left off if the server is restarted.
+pub struct PregenerationPlugin;
+impl Plugin for PregenerationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(PostStartup, setup)
+            .add_systems(Update, (handle_pregeneration_commands, pregenerate).chain());
+    }
+}
+
+/// Pre-generate the chunks within this radius of spawn when the server starts.
+#[derive(Resource)]
+pub struct PregenerateOnStartup(pub u32);
+
+/// The terrain generator the world was created with, without the pre-generated chunks.
+#[derive(Resource)]
+pub struct TerrainGeneratorSource(pub Arc<dyn TerrainGenerator>);
+
+/// An unfinished pre-generation, stored with the world so it can be resumed.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PregenerationJob {
+    /// Block position the chunks are generated around
+    pub center: IVec2,
+    /// Radius in blocks
+    pub radius: u32,
+}
+
+#[derive(Resource)]
+struct Pregeneration {
+    generator: Arc<dyn TerrainGenerator>,
+    // Chunks that may be generated before having to wait, refilled at the configured rate.
+    budget: f32,
+    rate: f32,
+    // Chunk columns that haven't been generated yet, the closest to the center is last.
+    remaining: Vec<IVec2>,
+    total: usize,
+    task: Option<Task<Result<(), String>>>,
+    progress_timer: Timer,
+}
+
+impl Pregeneration {
+    fn start(&mut self, job: &PregenerationJob, database: &Database) {
+        let chunk_size = Chunk::SIZE as i32;
+        let center = job.center.div_euclid(IVec2::splat(chunk_size));
+        let radius = (job.radius as i32).div_ceil(chunk_size);
+
+        let mut columns = Vec::new();
+        for x in -radius..=radius {
+            for z in -radius..=radius {
+                if x * x + z * z <= radius * radius {
+                    columns.push((center + IVec2::new(x, z)) * chunk_size);
+                }
+            }
+        }
+        self.total = columns.len();
+
+        let stored = stored_columns(database);
+        columns.retain(|column| !stored.contains(column));
+        columns.sort_by_key(|column| std::cmp::Reverse((*column - job.center).length_squared()));
+        self.remaining = columns;
+
+        self.progress_timer.reset();
+    }
+
+    fn stop(&mut self) {
+        self.remaining.clear();
+        self.task = None;
+    }
+
+    fn is_running(&self) -> bool {
+        !self.remaining.is_empty() || self.task.is_some()
+    }
+
+    fn log_progress(&self) {
+        let done = self.total - self.remaining.len() - self.task.is_some() as usize;
+        info!(
+            "Pre-generated {}/{} chunk columns ({:.1}%)",
+            done,
+            self.total,
+            done as f32 / self.total.max(1) as f32 * 100.0
+        );
+    }
+}
+
+fn spawn_column(world_properties: &WorldProperties) -> IVec2 {
+    let center = world_properties.spawn_point.center;
+    IVec2::new(center.x, center.z)
+}
+
+fn setup(
+    mut commands: Commands,
+    settings: Res<Settings>,
+    database: Res<Database>,
+    generator: Res<TerrainGeneratorSource>,
+    pregenerate_on_startup: Option<Res<PregenerateOnStartup>>,
+    mut world_properties: ResMut<WorldProperties>,
+) {
+    let mut pregeneration = Pregeneration {
+        generator: generator.0.clone(),
+        budget: 0.0,
+        rate: settings.pregeneration_rate as f32,
+        remaining: Vec::new(),
+        total: 0,
+        task: None,
+        progress_timer: Timer::from_seconds(10.0, TimerMode::Repeating),
+    };
+
+    if let Some(radius) = pregenerate_on_startup {
+        world_properties.pregeneration = Some(PregenerationJob {
+            center: spawn_column(&world_properties),
+            radius: radius.0,
+        });
+    }
+
+    if let Some(job) = &world_properties.pregeneration {
+        info!(
+            "Pre-generating the chunks within {} blocks of {}",
+            job.radius, job.center
+        );
+        pregeneration.start(job, &database);
+        pregeneration.log_progress();
+    }
+
+    commands.insert_resource(pregeneration);
+}
+
+fn handle_pregeneration_commands(
+    database: Res<Database>,
+    mut pregeneration: ResMut<Pregeneration>,
+    mut world_properties: ResMut<WorldProperties>,
+    mut console_commands: EventReader<ConsoleCommand>,
+) {
+    for command in console_commands.read() {
+        if command.name != "pregenerate" {
+            continue;
+        }
+
+        let args: Vec<&str> = command.args.iter().map(|arg| arg.as_str()).collect();
+        match args.as_slice() {
+            [] if pregeneration.is_running() => pregeneration.log_progress(),
+            [] => info!("No chunks are being pre-generated"),
+            ["stop"] => {
+                if pregeneration.is_running() {
+                    pregeneration.stop();
+                    world_properties.pregeneration = None;
+                    info!("Stopped pre-generating, the chunks that are done are kept");
+                } else {
+                    info!("No chunks are being pre-generated");
+                }
+            }
+            [radius] => {
+                let Ok(radius) = radius.parse::<u32>() else {
+                    info!("The radius must be a positive number of blocks");
+                    continue;
+                };
+                let job = PregenerationJob {
+                    center: spawn_column(&world_properties),
+                    radius,
+                };
+                info!(
+                    "Pre-generating the chunks within {} blocks of {}",
+                    job.radius, job.center
+                );
+                pregeneration.start(&job, &database);
+                pregeneration.log_progress();
+                world_properties.pregeneration = Some(job);
+            }
+            _ => info!("Usage: pregenerate [<radius>|stop]"),
+        }
+    }
+}
+
+fn pregenerate(
+    time: Res<Time>,
+    database: Res<Database>,
+    mut pregeneration: ResMut<Pregeneration>,
+    mut world_properties: ResMut<WorldProperties>,
+) {
+    if let Some(task) = pregeneration.task.as_mut() {
+        let Some(result) = future::block_on(future::poll_once(task)) else {
+            return;
+        };
+        pregeneration.task = None;
+
+        if let Err(e) = result {
+            error!(
+                "Failed to store pre-generated chunks, stopping.\nError: {}",
+                e
+            );
+            pregeneration.stop();
+            world_properties.pregeneration = None;
+            return;
+        }
+    }
+
+    if !pregeneration.is_running() {
+        if world_properties.pregeneration.is_some() {
+            pregeneration.log_progress();
+            info!("Finished pre-generating");
+            world_properties.pregeneration = None;
+        }
+        return;
+    }
+
+    if pregeneration
+        .progress_timer
+        .tick(time.delta())
+        .just_finished()
+    {
+        pregeneration.log_progress();
+    }
+
+    // The budget can't be saved up for more than a second, so the rate stays even.
+    let rate = pregeneration.rate;
+    pregeneration.budget = (pregeneration.budget + rate * time.delta_seconds())
+        .min(rate.max(CHUNKS_PER_COLUMN as f32));
+    if pregeneration.budget < CHUNKS_PER_COLUMN as f32 {
+        return;
+    }
+    pregeneration.budget -= CHUNKS_PER_COLUMN as f32;
+
+    // Columns are generated one at a time so only one thread is ever busy with it.
+    let column = pregeneration.remaining.pop().unwrap();
+    let generator = pregeneration.generator.clone();
+    let database = database.clone();
+    pregeneration.task = Some(AsyncComputeTaskPool::get().spawn(async move {
+        let chunks: Vec<(IVec3, Chunk)> = (BOTTOM..TOP)
+            .step_by(Chunk::SIZE)
+            .map(|y| {
+                let chunk_position = IVec3::new(column.x, y, column.y);
+                (chunk_position, generator.generate_chunk(chunk_position))
+            })
+            .collect();
+        store_chunks(&database, &chunks)
+    }));
+}
+
+/// Terrain generator that uses the pre-generated chunks when they are available.
+pub struct StoredTerrain {
+    generator: Arc<dyn TerrainGenerator>,
+    database: Database,
+}
+
+impl StoredTerrain {
+    pub fn new(generator: Arc<dyn TerrainGenerator>, database: Database) -> Self {
+        database
+            .get_connection()
+            .execute(
+                "CREATE TABLE IF NOT EXISTS pregenerated_chunks (
+                    x INTEGER NOT NULL,
+                    y INTEGER NOT NULL,
+                    z INTEGER NOT NULL,
+                    blocks BLOB NOT NULL,
+                    PRIMARY KEY (x, y, z)
+                )",
+                [],
+            )
+            .unwrap();
+
+        Self {
+            generator,
+            database,
+        }
+    }
+}
+
+impl TerrainGenerator for StoredTerrain {
+    fn generate_chunk(&self, chunk_position: IVec3) -> Chunk {
+        let conn = self.database.get_connection();
+        let mut stmt = conn
+            .prepare_cached(
+                "SELECT blocks FROM pregenerated_chunks WHERE x = ? AND y = ? AND z = ?",
+            )
+            .unwrap();
+        let stored: Option<Vec<u8>> = stmt
+            .query_row(
+                [chunk_position.x, chunk_position.y, chunk_position.z],
+                |row| row.get(0),
+            )
+            .ok();
+
+        match stored.and_then(|data| decode_chunk(&data)) {
+            Some(chunk) => chunk,
+            None => self.generator.generate_chunk(chunk_position),
+        }
+    }
+}
+
+// The positions of the chunk columns that have been pre-generated. Columns are stored all at
+// once, so a column is done if any of its chunks are stored.
+fn stored_columns(database: &Database) -> HashSet<IVec2> {
+    let conn = database.get_connection();
+    let mut stmt = conn
+        .prepare("SELECT DISTINCT x, z FROM pregenerated_chunks")
+        .unwrap();
+    let columns = stmt
+        .query_map([], |row| Ok(IVec2::new(row.get(0)?, row.get(1)?)))
+        .unwrap()
+        .map(|column| column.unwrap())
+        .collect();
+    columns
+}
+
+fn store_chunks(database: &Database, chunks: &[(IVec3, Chunk)]) -> Result<(), String> {
+    let mut conn = database.get_connection();
+    let transaction = conn.transaction().map_err(|e| e.to_string())?;
+    {
+        let mut stmt = transaction
+            .prepare(
+                "INSERT OR REPLACE INTO pregenerated_chunks (x, y, z, blocks) VALUES (?, ?, ?, ?)",
+            )
+            .map_err(|e| e.to_string())?;
+        for (position, chunk) in chunks {
+            stmt.execute(rusqlite::params![
+                position.x,
+                position.y,
+                position.z,
+                encode_chunk(chunk)
+            ])
+            .map_err(|e| e.to_string())?;
+        }
+    }
+    transaction.commit().map_err(|e| e.to_string())
+}
+
+// Everything the terrain generator puts in a chunk. The terrain features are kept so the parts
+// of them that reach into the neighbouring chunks are placed when those are generated.
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+struct StoredChunk {
+    // Uniform chunks are stored as a single block.
+    blocks: Vec<BlockId>,
+    // Block index and state of the blocks that have one
+    block_states: Vec<(usize, BlockState)>,
+    terrain_features: Vec<StoredTerrainFeature>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+struct StoredTerrainFeature {
+    // Sorted by position so the same feature is always stored the same way.
+    blocks: Vec<(IVec3, BlockId, Option<BlockState>)>,
+    can_replace: Vec<BlockId>,
+}
+
+impl StoredChunk {
+    fn new(chunk: &Chunk) -> Self {
+        let blocks = if chunk.is_uniform() {
+            vec![chunk[0]]
+        } else {
+            chunk.blocks.clone()
+        };
+
+        let block_states = if chunk.is_uniform() {
+            Vec::new()
+        } else {
+            (0..Chunk::SIZE.pow(3))
+                .filter_map(|index| Some((index, chunk.get_block_state(&index)?)))
+                .collect()
+        };
+
+        let terrain_features = chunk
+            .terrain_features
+            .iter()
+            .map(|terrain_feature| {
+                let mut blocks: Vec<_> = terrain_feature
+                    .blocks
+                    .iter()
+                    .map(|(position, (block_id, block_state))| (*position, *block_id, *block_state))
+                    .collect();
+                blocks.sort_unstable_by_key(|(position, _, _)| position.to_array());
+
+                let mut can_replace: Vec<_> = terrain_feature.can_replace.iter().cloned().collect();
+                can_replace.sort_unstable();
+
+                StoredTerrainFeature {
+                    blocks,
+                    can_replace,
+                }
+            })
+            .collect();
+
+        Self {
+            blocks,
+            block_states,
+            terrain_features,
+        }
+    }
+
+    fn into_chunk(self) -> Option<Chunk> {
+        let mut chunk = Chunk::default();
+        if self.blocks.len() == 1 {
+            chunk.make_uniform(self.blocks[0]);
+        } else if self.blocks.len() == Chunk::SIZE.pow(3) {
+            chunk.blocks = self.blocks;
+        } else {
+            return None;
+        }
+
+        for (index, block_state) in self.block_states {
+            if index >= chunk.blocks.len() {
+                return None;
+            }
+            chunk.set_block_state(index, Some(block_state));
+        }
+
+        chunk.terrain_features = self
+            .terrain_features
+            .into_iter()
+            .map(|terrain_feature| TerrainFeature {
+                blocks: terrain_feature
+                    .blocks
+                    .into_iter()
+                    .map(|(position, block_id, block_state)| (position, (block_id, block_state)))
+                    .collect(),
+                can_replace: terrain_feature.can_replace.into_iter().collect(),
+            })
+            .collect();
+
+        return Some(chunk);
+    }
+}
+
+fn encode_chunk(chunk: &Chunk) -> Vec<u8> {
+    let bytes = bincode::serialize(&StoredChunk::new(chunk)).unwrap();
+    zstd::bulk::compress(&bytes, 3).unwrap()
+}
+
+fn decode_chunk(data: &[u8]) -> Option<Chunk> {
+    let bytes = zstd::decode_all(data).ok()?;
+    let stored: StoredChunk = bincode::deserialize(&bytes).ok()?;
+    stored.into_chunk()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::world::Earth;
+
+    // The first chunk around the origin with a terrain feature that reaches out of it, like a
+    // tree at its edge.
+    fn chunk_with_crossing_feature(generator: &Earth) -> (IVec3, Chunk) {
+        let size = Chunk::SIZE as i32;
+        for x in -8..8 {
+            for z in -8..8 {
+                for y in 0..4 {
+                    let chunk_position = IVec3::new(x, y, z) * size;
+                    let chunk = generator.generate_chunk(chunk_position);
+                    let crosses_border = chunk.terrain_features.iter().any(|terrain_feature| {
+                        terrain_feature.blocks.keys().any(|position| {
+                            let position = *position - chunk_position;
+                            position.cmplt(IVec3::ZERO).any()
+                                || position.cmpge(IVec3::splat(size)).any()
+                        })
+                    });
+                    if crosses_border {
+                        return (chunk_position, chunk);
+                    }
+                }
+            }
+        }
+        panic!("No terrain feature crosses a chunk border around the origin");
+    }
+
+    #[test]
+    fn stored_chunks_are_the_same_as_generated() {
+        let blocks = crate::assets::load_blocks();
+        let generator = Earth::new(0, blocks);
+        let (chunk_position, chunk) = chunk_with_crossing_feature(&generator);
+
+        let database_path = std::env::temp_dir().join(format!(
+            "fmc_pregeneration_test_{}.sqlite",
+            std::process::id()
+        ));
+        let database = Database::new(database_path.to_string_lossy().into_owned());
+        let stored_terrain = StoredTerrain::new(Arc::new(generator.clone()), database.clone());
+        store_chunks(&database, &[(chunk_position, chunk)]).unwrap();
+
+        let stored = stored_terrain.generate_chunk(chunk_position);
+        let generated = generator.generate_chunk(chunk_position);
+        std::fs::remove_file(&database_path).ok();
+
+        assert_eq!(StoredChunk::new(&stored), StoredChunk::new(&generated));
+    }
+}
@@ -0,0 +1,308 @@
+use std::collections::{HashMap, HashSet};
+
+use fmc::{
+    blocks::{BlockId, Blocks, BLOCK_CONFIG_PATH},
+    prelude::*,
+    world::{blueprints::TerrainFeature, chunk::Chunk},
+};
+use serde::Deserialize;
+
+use super::{
+    biomes::{Biomes, BIOME_PATH},
+    config_files::read_config_files,
+};
+
+pub const STRUCTURE_PATH: &str = "resources/server/structures";
+
+/// The world is divided into square regions of this many blocks, each region can contain one
+/// structure. Structures are kept inside their region so that only the chunks of the region have
+/// to consider it.
+pub const REGION_SIZE: i32 = Chunk::SIZE as i32 * 8;
+
+// How far below a structure air and liquid is filled in with its foundation block.
+const FOUNDATION_DEPTH: i32 = 8;
+
+// The most the surface can vary under the corners of a structure for it to be placed.
+const MAX_SLOPE: i32 = 4;
+
+/// A prefab building placed on the terrain surface.
+#[derive(Clone)]
+pub struct Structure {
+    /// Size in blocks
+    pub size: IVec3,
+    // Indexed by y * size.z * size.x + z * size.x + x, None leaves the terrain as it is.
+    blocks: Vec<Option<BlockId>>,
+    // The layer of the structure that is placed on the first block above the surface.
+    ground_level: i32,
+    // Blocks the structure is allowed to replace, the terrain it is built over.
+    replace: HashSet<BlockId>,
+    foundation: Option<BlockId>,
+    // Blocks the foundation is allowed to replace
+    fill: HashSet<BlockId>,
+    /// Chance the structure is placed in a region.
+    pub chance: f32,
+    // Indices of the biomes the structure is placed in, all biomes if None.
+    biomes: Option<Vec<usize>>,
+}
+
+impl Structure {
+    fn block(&self, position: IVec3) -> Option<BlockId> {
+        let index = position.y * self.size.z * self.size.x + position.z * self.size.x + position.x;
+        self.blocks[index as usize]
+    }
+
+    pub fn allowed_in_biome(&self, biome_index: usize) -> bool {
+        match &self.biomes {
+            Some(biomes) => biomes.contains(&biome_index),
+            None => true,
+        }
+    }
+
+    /// Find the height the structure should be placed at when its corner is at x, z, so that it
+    /// sits on the surface under its center. 'surface_height' gives the surface height of a
+    /// column. None if the terrain is too steep or under water.
+    pub fn fit_to_terrain(
+        &self,
+        origin_x: i32,
+        origin_z: i32,
+        surface_height: impl Fn(i32, i32) -> Option<i32>,
+    ) -> Option<IVec3> {
+        let center = surface_height(origin_x + self.size.x / 2, origin_z + self.size.z / 2)?;
+
+        for (x, z) in [
+            (0, 0),
+            (self.size.x - 1, 0),
+            (0, self.size.z - 1),
+            (self.size.x - 1, self.size.z - 1),
+        ] {
+            let corner = surface_height(origin_x + x, origin_z + z)?;
+            if (corner - center).abs() > MAX_SLOPE {
+                return None;
+            }
+        }
+
+        Some(IVec3::new(
+            origin_x,
+            center + 1 - self.ground_level,
+            origin_z,
+        ))
+    }
+
+    /// Construct the structure placed at 'origin', and the foundation below it. The terrain
+    /// features should be recorded by the chunk that contains the origin, the parts that are
+    /// inside other chunks are placed when those are generated.
+    pub fn construct(&self, origin: IVec3) -> Vec<TerrainFeature> {
+        let mut terrain_feature = TerrainFeature {
+            blocks: HashMap::new(),
+            can_replace: self.replace.union(&self.fill).cloned().collect(),
+        };
+
+        for x in 0..self.size.x {
+            for z in 0..self.size.z {
+                for y in 0..self.size.y {
+                    let position = IVec3::new(x, y, z);
+                    if let Some(block_id) = self.block(position) {
+                        terrain_feature
+                            .blocks
+                            .insert(origin + position, (block_id, None));
+                    }
+                }
+            }
+        }
+
+        let Some(foundation) = self.foundation else {
+            return vec![terrain_feature];
+        };
+
+        // Fill in the gaps between the bottom of the structure and the terrain. The foundation
+        // may only replace what it fills, so it is a feature of its own.
+        let mut foundation_feature = TerrainFeature {
+            blocks: HashMap::new(),
+            can_replace: self.fill.clone(),
+        };
+
+        for x in 0..self.size.x {
+            for z in 0..self.size.z {
+                // Only under the parts of the bottom layer that have blocks
+                if self.block(IVec3::new(x, 0, z)).is_none() {
+                    continue;
+                }
+
+                for depth in 1..=FOUNDATION_DEPTH {
+                    foundation_feature
+                        .blocks
+                        .insert(origin + IVec3::new(x, -depth, z), (foundation, None));
+                }
+            }
+        }
+
+        return vec![terrain_feature, foundation_feature];
+    }
+}
+
+/// Structures are stored as layers of text, bottom layer first. Each layer is a list of rows
+/// along the z axis, and each character of a row is a block along the x axis. The palette maps
+/// the characters to block names, a space leaves the terrain as it is.
+#[derive(Deserialize)]
+struct StructureJson {
+    palette: HashMap<char, String>,
+    layers: Vec<Vec<String>>,
+    // How many of the layers are below the surface
+    #[serde(default)]
+    ground_level: i32,
+    // Blocks the structure can be built over, e.g. the surface and plants.
+    replace: Vec<String>,
+    #[serde(default)]
+    foundation: Option<String>,
+    // Blocks the foundation fills in, e.g. air and water
+    #[serde(default)]
+    fill: Vec<String>,
+    chance: f32,
+    #[serde(default)]
+    biomes: Vec<String>,
+}
+
+/// Load the structures from the structure directory. They are sorted by name so they are always
+/// considered in the same order.
+pub fn load_structures(blocks: &Blocks, biomes: &Biomes) -> Vec<Structure> {
+    let structure_jsons: Vec<(String, StructureJson)> =
+        read_config_files(STRUCTURE_PATH, "structure");
+
+    let mut errors = Vec::new();
+
+    let validate_block = |errors: &mut Vec<String>, structure_name: &str, block_name: &str| {
+        if blocks.contains_block(block_name) {
+            true
+        } else {
+            errors.push(format!(
+                "The structure '{}' references a block with the name '{}', but no block by that \
+                name exists. Make sure a block by the same name is present at '{}'",
+                structure_name, block_name, BLOCK_CONFIG_PATH
+            ));
+            false
+        }
+    };
+
+    let mut structures = Vec::with_capacity(structure_jsons.len());
+
+    for (structure_name, structure) in structure_jsons {
+        let size = IVec3::new(
+            structure
+                .layers
+                .iter()
+                .flatten()
+                .map(|row| row.chars().count())
+                .max()
+                .unwrap_or(0) as i32,
+            structure.layers.len() as i32,
+            structure
+                .layers
+                .iter()
+                .map(|layer| layer.len())
+                .max()
+                .unwrap_or(0) as i32,
+        );
+
+        if size.cmpeq(IVec3::ZERO).any() {
+            errors.push(format!("The structure '{}' is empty.", structure_name));
+            continue;
+        }
+
+        if size.x > REGION_SIZE || size.z > REGION_SIZE {
+            errors.push(format!(
+                "The structure '{}' is too large, it can't be wider than {} blocks.",
+                structure_name, REGION_SIZE
+            ));
+        }
+
+        let mut palette = HashMap::new();
+        for (character, block_name) in structure.palette.iter() {
+            if validate_block(&mut errors, &structure_name, block_name) {
+                palette.insert(*character, blocks.get_id(block_name));
+            }
+        }
+
+        let mut structure_blocks = vec![None; (size.x * size.y * size.z) as usize];
+        for (y, layer) in structure.layers.iter().enumerate() {
+            for (z, row) in layer.iter().enumerate() {
+                for (x, character) in row.chars().enumerate() {
+                    if character == ' ' {
+                        continue;
+                    }
+
+                    let Some(block_id) = palette.get(&character) else {
+                        if !structure.palette.contains_key(&character) {
+                            errors.push(format!(
+                                "The structure '{}' uses the character '{}', but it is not in \
+                                its palette.",
+                                structure_name, character
+                            ));
+                        }
+                        continue;
+                    };
+
+                    let index = y * (size.z * size.x) as usize + z * size.x as usize + x;
+                    structure_blocks[index] = Some(*block_id);
+                }
+            }
+        }
+
+        let foundation = match &structure.foundation {
+            Some(block_name) if validate_block(&mut errors, &structure_name, block_name) => {
+                Some(blocks.get_id(block_name))
+            }
+            _ => None,
+        };
+
+        let mut replace = HashSet::with_capacity(structure.replace.len());
+        for block_name in structure.replace.iter() {
+            if validate_block(&mut errors, &structure_name, block_name) {
+                replace.insert(blocks.get_id(block_name));
+            }
+        }
+
+        let mut fill = HashSet::with_capacity(structure.fill.len());
+        for block_name in structure.fill.iter() {
+            if validate_block(&mut errors, &structure_name, block_name) {
+                fill.insert(blocks.get_id(block_name));
+            }
+        }
+
+        let mut biome_indices = Vec::with_capacity(structure.biomes.len());
+        for biome_name in structure.biomes.iter() {
+            match biomes.iter().position(|biome| &biome.name == biome_name) {
+                Some(index) => biome_indices.push(index),
+                None => errors.push(format!(
+                    "The structure '{}' is restricted to a biome with the name '{}', but no \
+                    biome by that name exists. Make sure a biome by the same name is present at \
+                    '{}'",
+                    structure_name, biome_name, BIOME_PATH
+                )),
+            }
+        }
+
+        structures.push(Structure {
+            size,
+            blocks: structure_blocks,
+            ground_level: structure.ground_level,
+            replace,
+            foundation,
+            fill,
+            chance: structure.chance,
+            biomes: if biome_indices.is_empty() {
+                None
+            } else {
+                Some(biome_indices)
+            },
+        });
+    }
+
+    if !errors.is_empty() {
+        panic!(
+            "Startup failed while validating the structures.\n{}",
+            errors.join("\n")
+        );
+    }
+
+    return structures;
+}
@@ -1,12 +1,19 @@
+use std::sync::Arc;
+
 use fmc::{
-    blocks::Blocks,
+    blocks::{BlockId, Blocks},
     noise::Noise,
     prelude::*,
     world::{chunk::Chunk, TerrainGenerator},
 };
-use rand::SeedableRng;
+use rand::{Rng, SeedableRng};
 
-use super::biomes::Biomes;
+use super::{
+    biomes::{Biome, Biomes},
+    column_cache::ColumnCache,
+    ores::{load_ores, Ore},
+    structures::{load_structures, Structure, REGION_SIZE},
+};
 
 // The heighest point relative to the base height 3d noise can extend to create terrain.
 const MAX_HEIGHT: i32 = 120;
@@ -16,13 +23,147 @@ const MAX_HEIGHT: i32 = 120;
 // deep we are, in order to know which blocks to use when at the surface.
 const Y_OFFSET: usize = 4;
 
+// The density is generated with a border of one block around the chunk, and below it.
+const PADDED_SIZE: usize = Chunk::SIZE + 2;
+const PADDED_HEIGHT: usize = Chunk::SIZE + Y_OFFSET + 1;
+
+// The terrain height out at sea, it keeps the sea floor flat.
+const SEA_TERRAIN_HEIGHT: f32 = 0.5;
+
+// How far from the center of a river its valley extends, measured in river noise.
+const RIVER_WIDTH: f32 = 0.08;
+// Depth of the river bed below sea level at the center of a river.
+const RIVER_DEPTH: f32 = 4.0;
+// How far from the center of a river, as a fraction of its width, the banks extend.
+const RIVER_BANK_WIDTH: f32 = 0.4;
+
+// Temperature below which water freezes and the ground is covered in snow.
+const FREEZING_TEMPERATURE: f32 = -0.2;
+// How much colder it gets per block above sea level, so mountains get snow caps.
+const COOLING_PER_BLOCK: f32 = 0.004;
+
+// How many chunk columns the 2d noise is cached for.
+const COLUMN_CACHE_SIZE: usize = 2048;
+// How many regions the placement of their structure is cached for.
+const STRUCTURE_CACHE_SIZE: usize = 256;
+
+// Below this height caves are carved no matter what is above them.
+const CAVE_DECAY_POINT: i32 = -32;
+// Tunnels are carved where the tunnel noise is below this value, it decides their width.
+const TUNNEL_RADIUS: f32 = 0.04;
+
+#[derive(Clone)]
 pub struct Earth {
     biomes: Biomes,
+    ores: Vec<Ore>,
+    structures: Vec<Structure>,
     continents: Noise,
     terrain_height: Noise,
     terrain_shape: Noise,
     caves: Noise,
+    tunnels: Noise,
+    rivers: Noise,
+    temperature: Noise,
+    humidity: Noise,
     seed: i32,
+    // If set, the land is confined to a circle of this radius around the origin.
+    island_radius: Option<f32>,
+    // Shared by all clones, so that all the generation tasks benefit from it.
+    column_noise: Arc<ColumnCache<ColumnNoise>>,
+    // The structure of each region and where it is placed, keyed by the corner of the region.
+    // Finding the height is expensive, so it is only done once for all the chunks of the region.
+    structure_placements: Arc<ColumnCache<Option<(usize, IVec3)>>>,
+}
+
+// The parts of the terrain generation that are the same for a whole block column.
+struct ColumnShape {
+    base_height: f32,
+    terrain_height: f32,
+    river_bed: f32,
+}
+
+impl ColumnShape {
+    // Takes the raw noise values, 'river_distance' should already be divided by the river width.
+    fn new(continents: f32, terrain_height: f32, river_distance: f32) -> Self {
+        Self {
+            base_height: continents * MAX_HEIGHT as f32,
+            terrain_height,
+            // The valley walls rise with the distance from the river, at the edge of the valley
+            // they are at MAX_HEIGHT, so no terrain is cut off abruptly.
+            river_bed: -RIVER_DEPTH + river_distance.powi(2) * MAX_HEIGHT as f32,
+        }
+    }
+
+    // Turns the terrain shape noise into the density of the terrain, solid if above 0.
+    fn density(&self, terrain_shape: f32, block_height: f32) -> f32 {
+        // Amount the density should be decreased by per block above the base height
+        // for the maximum height to be MAX_HEIGHT.
+        // MAX_HEIGHT * DECREMENT / terrain_height_max = 1
+        const DECREMENT: f32 = 1.5 / MAX_HEIGHT as f32;
+        let mut compression = (block_height - self.base_height) * DECREMENT / self.terrain_height;
+        if compression < 0.0 {
+            // Below surface, extra compression
+            compression *= 3.0;
+        }
+        // Decrease density if above base height, increase if below
+        let mut density = terrain_shape - compression;
+
+        if block_height > self.river_bed {
+            // Everything above the river bed is carved out
+            density = density.min((self.river_bed - block_height) * DECREMENT);
+        }
+
+        density
+    }
+}
+
+// The climate of each block column in a chunk, indexed by x << 4 | z
+struct Climate {
+    // Index of the biome in 'Earth::biomes'
+    biomes: Vec<usize>,
+    // Temperature at sea level
+    temperature: Vec<f32>,
+}
+
+impl Climate {
+    // If it is cold enough to freeze at the height in the column at 'index'.
+    fn is_freezing(&self, index: usize, block_height: i32) -> bool {
+        self.temperature[index] - block_height.max(0) as f32 * COOLING_PER_BLOCK
+            < FREEZING_TEMPERATURE
+    }
+}
+
+// The 2d noise of a chunk column, indexed by x << 4 | z. It is the same for every chunk in the
+// column, so it is only computed once and cached.
+struct ColumnNoise {
+    climate: Climate,
+    // Continent noise, the base height as a fraction of MAX_HEIGHT
+    base_height: Vec<f32>,
+    // Terrain height noise scaled by the biomes
+    terrain_height: Vec<f32>,
+    // River noise, not yet divided by the river width
+    rivers: Vec<f32>,
+}
+
+impl ColumnNoise {
+    // How likely it is for there to be liquid above the column at 'index', from 0 on dry land
+    // to 1 in the sea and rivers.
+    fn wetness(&self, index: usize) -> f32 {
+        // Fades out from the shore line
+        let sea = (4.0 - self.base_height[index] * MAX_HEIGHT as f32) / 4.0;
+        // Fades out from the edge of the river water to the edge of its banks.
+        let river_distance = self.rivers[index] / RIVER_WIDTH;
+        let river = (RIVER_BANK_WIDTH * 1.25 - river_distance) / (RIVER_BANK_WIDTH * 0.5);
+        sea.max(river).clamp(0.0, 1.0)
+    }
+
+    fn shape(&self, index: usize) -> ColumnShape {
+        ColumnShape::new(
+            self.base_height[index],
+            self.terrain_height[index],
+            (self.rivers[index] / RIVER_WIDTH).min(1.0),
+        )
+    }
 }
 
 impl Earth {
@@ -71,10 +212,12 @@ impl Earth {
 
         // When out at sea bottom out the terrain height gradually from the shore, so big
         // landmasses don't poke out.
-        let terrain_height =
-            continents
-                .clone()
-                .range(0.0, -0.05, terrain_height, Noise::constant(0.5));
+        let terrain_height = continents.clone().range(
+            0.0,
+            -0.05,
+            terrain_height,
+            Noise::constant(SEA_TERRAIN_HEIGHT),
+        );
 
         let freq = 1.0 / 2.0f32.powi(8);
         let high = Noise::perlin(freq, seed + 2).fbm(4, 0.5, 2.0);
@@ -111,6 +254,21 @@ impl Earth {
         //            .abs()
         //    );
 
+        // Snaking tunnels. The absolute value of 3d noise under some threshold forms thin sheets,
+        // where two such sheets intersect there is a tube. It is squashed vertically so the
+        // tunnels mostly run horizontally and stay walkable.
+        let freq = 1.0 / 64.0;
+        let tunnels = Noise::perlin(freq, seed + 10)
+            .with_frequency(freq, freq * 2.0, freq)
+            .fbm(2, 0.5, 2.0)
+            .abs()
+            .max(
+                Noise::perlin(freq, seed + 11)
+                    .with_frequency(freq, freq * 2.0, freq)
+                    .fbm(2, 0.5, 2.0)
+                    .abs(),
+            );
+
         // Visualization: https://www.shadertoy.com/view/stccDB
         let freq = 0.01;
         let cave_main = Noise::perlin(freq, seed + 5)
@@ -130,76 +288,294 @@ impl Earth {
             Noise::constant(1.0),
         );
 
+        // Rivers follow the lines where the noise crosses zero, this makes them winding and
+        // continuous. They are carved down to below sea level, so they always fill with water and
+        // run into the ocean wherever they reach the coast.
+        let freq = 1.0 / 2.0f32.powi(9);
+        let rivers = Noise::perlin(freq, seed + 9)
+            .with_frequency(freq, 0.0, freq)
+            .fbm(3, 0.5, 2.0)
+            .abs();
+
+        // Biomes are large, so the climate changes slowly.
+        let freq = 1.0 / 2.0f32.powi(10);
+        let temperature = Noise::perlin(freq, seed + 7)
+            .with_frequency(freq, 0.0, freq)
+            .fbm(4, 0.5, 2.0);
+        let humidity = Noise::perlin(freq, seed + 8)
+            .with_frequency(freq, 0.0, freq)
+            .fbm(4, 0.5, 2.0);
+
+        let biomes = Biomes::load(blocks);
+        let ores = load_ores(blocks, &biomes);
+        let structures = load_structures(blocks, &biomes);
+
         Self {
-            biomes: Biomes::load(blocks),
+            biomes,
+            ores,
+            structures,
             continents,
             terrain_height,
             terrain_shape,
             caves,
+            tunnels,
+            rivers,
+            temperature,
+            humidity,
             seed,
+            island_radius: None,
+            column_noise: Arc::new(ColumnCache::new(COLUMN_CACHE_SIZE)),
+            structure_placements: Arc::new(ColumnCache::new(STRUCTURE_CACHE_SIZE)),
         }
     }
 
-    fn generate_terrain(&self, chunk_position: IVec3, chunk: &mut Chunk) {
-        let (mut terrain_shape, _, _) = self.terrain_shape.generate_3d(
-            chunk_position.x as f32,
-            chunk_position.y as f32,
-            chunk_position.z as f32,
-            Chunk::SIZE,
-            Chunk::SIZE + Y_OFFSET,
-            Chunk::SIZE,
+    /// A single island surrounded by ocean, centered on the origin.
+    pub fn island(seed: i32, blocks: &Blocks, radius: u32) -> Self {
+        Self {
+            island_radius: Some(radius as f32),
+            ..Self::new(seed, blocks)
+        }
+    }
+
+    // The continent noise of each column in an area, indexed by x << 4 | z. For islands it is
+    // lowered into ocean towards the edge of the island.
+    fn continents(&self, x: i32, z: i32, width: usize, depth: usize) -> Vec<f32> {
+        let (mut continents, _, _) = self
+            .continents
+            .generate_3d(x as f32, 0.0, z as f32, width, 1, depth);
+
+        if self.island_radius.is_none() {
+            return continents;
+        }
+
+        const OCEAN_FLOOR: f32 = -0.1;
+        const MIN_LAND: f32 = 0.02;
+        for i in 0..width {
+            for k in 0..depth {
+                let t = self.island_falloff(x + i as i32, z + k as i32);
+                let value = &mut continents[i * depth + k];
+                *value = value.max(MIN_LAND) * (1.0 - t) + OCEAN_FLOOR * t;
+            }
+        }
+
+        return continents;
+    }
+
+    // How far the column is from the island into the ocean around it, 0 on the island and 1 at
+    // its radius and beyond. The island is kept above sea level out to half of its radius, past
+    // that it slopes down to become ocean floor at the radius. Always 0 if the world isn't an
+    // island.
+    fn island_falloff(&self, x: i32, z: i32) -> f32 {
+        let Some(radius) = self.island_radius else {
+            return 0.0;
+        };
+        let distance = Vec2::new(x as f32, z as f32).length();
+        let t = ((distance / radius - 0.5) / 0.5).clamp(0.0, 1.0);
+        t * t * (3.0 - 2.0 * t)
+    }
+
+    /// The biome of the block column at x, z
+    pub fn get_biome(&self, x: i32, z: i32) -> &Biome {
+        self.biomes.get(self.biome_index(x, z))
+    }
+
+    fn biome_index(&self, x: i32, z: i32) -> usize {
+        self.column_noise(x, z).climate.biomes[column_index(x, z)]
+    }
+
+    /// Height of the highest solid block in the column at x, z. Caves and features are not
+    /// taken into account. None if the column is under water.
+    pub fn surface_height(&self, x: i32, z: i32) -> Option<i32> {
+        // Deep enough to reach the bottom of any sea
+        const MIN_HEIGHT: i32 = -32;
+        let height = (MAX_HEIGHT - MIN_HEIGHT) as usize + 1;
+
+        let column = self.column_noise(x, z).shape(column_index(x, z));
+
+        let (terrain_shape, _, _) =
+            self.terrain_shape
+                .generate_3d(x as f32, MIN_HEIGHT as f32, z as f32, 1, height, 1);
+
+        for (y, terrain_shape) in terrain_shape.into_iter().enumerate().rev() {
+            let block_height = MIN_HEIGHT + y as i32;
+            if column.density(terrain_shape, block_height as f32) > 0.0 {
+                // Everything at sea level and below is filled with water
+                return (block_height >= 0).then_some(block_height);
+            }
+        }
+
+        return None;
+    }
+
+    /// The structure of the region the chunk is in, and where it is placed, if its origin is
+    /// inside the chunk. The chunk with the origin records the structure, the rest of the chunks
+    /// it spans get their part from it.
+    fn find_structure(&self, chunk_position: IVec3) -> Option<(&Structure, IVec3)> {
+        let region_position = IVec2::new(
+            chunk_position.x.div_euclid(REGION_SIZE) * REGION_SIZE,
+            chunk_position.z.div_euclid(REGION_SIZE) * REGION_SIZE,
         );
+        let placement = self
+            .structure_placements
+            .get_or_insert_with(region_position, || self.place_structure(region_position));
 
-        let (base_height, _, _) = self.continents.generate_3d(
-            chunk_position.x as f32,
-            0.0,
-            chunk_position.z as f32,
-            Chunk::SIZE,
-            1,
-            Chunk::SIZE,
+        let (structure_index, origin) = (*placement)?;
+        let size = Chunk::SIZE as i32;
+        if origin.div_euclid(IVec3::splat(size)) * size != chunk_position {
+            return None;
+        }
+
+        return Some((&self.structures[structure_index], origin));
+    }
+
+    // Decide which structure is placed in the region and where, if any.
+    fn place_structure(&self, region_position: IVec2) -> Option<(usize, IVec3)> {
+        // Salted past the indices used by the blueprints and ores
+        let mut rng = rand::rngs::StdRng::seed_from_u64(hash_position(
+            self.seed,
+            IVec3::new(region_position.x, 0, region_position.y),
+            0xFFFE << 16,
+        ));
+
+        let (structure_index, structure) = self
+            .structures
+            .iter()
+            .enumerate()
+            .find(|(_, structure)| rng.gen::<f32>() < structure.chance)?;
+
+        let origin_x = region_position.x + rng.gen_range(0..=REGION_SIZE - structure.size.x);
+        let origin_z = region_position.y + rng.gen_range(0..=REGION_SIZE - structure.size.z);
+
+        let center_biome = self.biome_index(
+            origin_x + structure.size.x / 2,
+            origin_z + structure.size.z / 2,
         );
+        if !structure.allowed_in_biome(center_biome) {
+            return None;
+        }
 
-        let (terrain_height, _, _) = self.terrain_height.generate_3d(
-            chunk_position.x as f32,
-            0.0,
-            chunk_position.z as f32,
-            Chunk::SIZE,
-            1,
-            Chunk::SIZE,
+        let origin =
+            structure.fit_to_terrain(origin_x, origin_z, |x, z| self.surface_height(x, z))?;
+
+        return Some((structure_index, origin));
+    }
+
+    // The 2d noise of the chunk column that contains the block column at x, z.
+    fn column_noise(&self, x: i32, z: i32) -> Arc<ColumnNoise> {
+        let size = Chunk::SIZE as i32;
+        let column = IVec2::new(x.div_euclid(size) * size, z.div_euclid(size) * size);
+        self.column_noise
+            .get_or_insert_with(column, || self.generate_column_noise(column))
+    }
+
+    fn generate_column_noise(&self, column: IVec2) -> ColumnNoise {
+        let generate_2d = |noise: &Noise| {
+            noise
+                .generate_3d(
+                    column.x as f32,
+                    0.0,
+                    column.y as f32,
+                    Chunk::SIZE,
+                    1,
+                    Chunk::SIZE,
+                )
+                .0
+        };
+
+        let temperature = generate_2d(&self.temperature);
+        let humidity = generate_2d(&self.humidity);
+        let mut terrain_height = generate_2d(&self.terrain_height);
+
+        let mut climate = Climate {
+            biomes: Vec::with_capacity(Chunk::SIZE.pow(2)),
+            temperature: Vec::with_capacity(Chunk::SIZE.pow(2)),
+        };
+        for (index, (temperature, humidity)) in temperature.into_iter().zip(humidity).enumerate() {
+            climate
+                .biomes
+                .push(self.biomes.select(temperature, humidity));
+            climate.temperature.push(temperature);
+
+            // Around an island the terrain flattens out the same way it does at sea, so no land
+            // rises out of the ocean.
+            let t = self.island_falloff(
+                column.x + (index / Chunk::SIZE) as i32,
+                column.y + (index % Chunk::SIZE) as i32,
+            );
+            terrain_height[index] = terrain_height[index] * (1.0 - t) + SEA_TERRAIN_HEIGHT * t;
+            terrain_height[index] *= self.biomes.blended_height_scale(temperature, humidity);
+        }
+
+        ColumnNoise {
+            climate,
+            base_height: self.continents(column.x, column.y, Chunk::SIZE, Chunk::SIZE),
+            terrain_height,
+            rivers: generate_2d(&self.rivers),
+        }
+    }
+
+    // Returns the density, the caves are carved based on it too.
+    fn generate_terrain(
+        &self,
+        chunk_position: IVec3,
+        chunk: &mut Chunk,
+        column_noise: &ColumnNoise,
+    ) -> Vec<f32> {
+        // The density is generated for the chunk and the blocks bordering it, so that the slope of
+        // the terrain is known at its edges.
+        let (mut density, _, _) = self.terrain_shape.generate_3d(
+            (chunk_position.x - 1) as f32,
+            (chunk_position.y - 1) as f32,
+            (chunk_position.z - 1) as f32,
+            PADDED_SIZE,
+            PADDED_HEIGHT,
+            PADDED_SIZE,
         );
 
-        for x in 0..Chunk::SIZE {
-            for z in 0..Chunk::SIZE {
-                let index = x << 4 | z;
-                let base_height = base_height[index] * MAX_HEIGHT as f32;
-                let terrain_height = terrain_height[index];
-                for y in 0..Chunk::SIZE + Y_OFFSET {
-                    // Amount the density should be decreased by per block above the base height
-                    // for the maximum height to be MAX_HEIGHT.
-                    // MAX_HEIGHT * DECREMENT / terrain_height_max = 1
-                    const DECREMENT: f32 = 1.5 / MAX_HEIGHT as f32;
-                    let mut compression = ((chunk_position.y + y as i32) as f32 - base_height)
-                        * DECREMENT
-                        / terrain_height;
-                    if compression < 0.0 {
-                        // Below surface, extra compression
-                        compression *= 3.0;
-                    }
-                    let index = x * (Chunk::SIZE * (Chunk::SIZE + Y_OFFSET))
-                        + z * (Chunk::SIZE + Y_OFFSET)
-                        + y;
-                    // Decrease density if above base height, increase if below
-                    terrain_shape[index] -= compression;
+        for padded_x in 0..PADDED_SIZE {
+            for padded_z in 0..PADDED_SIZE {
+                let x = chunk_position.x + padded_x as i32 - 1;
+                let z = chunk_position.z + padded_z as i32 - 1;
+
+                // The bordering columns belong to the neighbouring chunk columns.
+                let neighbour;
+                let column_noise = if (1..=Chunk::SIZE).contains(&padded_x)
+                    && (1..=Chunk::SIZE).contains(&padded_z)
+                {
+                    column_noise
+                } else {
+                    neighbour = self.column_noise(x, z);
+                    &neighbour
+                };
+                let column = column_noise.shape(column_index(x, z));
+
+                for padded_y in 0..PADDED_HEIGHT {
+                    let block_height = (chunk_position.y + padded_y as i32 - 1) as f32;
+                    let index = density_index(padded_x, padded_y, padded_z);
+                    density[index] = column.density(density[index], block_height);
                 }
             }
         }
 
-        chunk.blocks = vec![0; Chunk::SIZE.pow(3)];
+        let base_height = &column_noise.base_height;
+        let climate = &column_noise.climate;
+
+        // 0 at the center of a river, 1 at the edge of its valley and beyond.
+        let river_distance: Vec<f32> = column_noise
+            .rivers
+            .iter()
+            .map(|distance| (distance / RIVER_WIDTH).min(1.0))
+            .collect();
+
+        let ice = Blocks::get().get_id("ice");
+        let snow = Blocks::get().get_id("snow");
 
-        let biome = self.biomes.get_biome();
+        chunk.blocks = vec![0; Chunk::SIZE.pow(3)];
 
         for x in 0..Chunk::SIZE {
             for z in 0..Chunk::SIZE {
+                let biome = self.biomes.get(climate.biomes[x << 4 | z]);
+
                 let mut layer = 0;
 
                 let base_height = base_height[x << 4 | z] * MAX_HEIGHT as f32;
@@ -208,10 +584,7 @@ impl Earth {
                 for y in Chunk::SIZE..Chunk::SIZE + Y_OFFSET {
                     // TODO: This needs to be converted to order xzy in simdnoise fork to make all
                     // access contiguous.
-                    let block_index = x * (Chunk::SIZE * (Chunk::SIZE + Y_OFFSET))
-                        + z * (Chunk::SIZE + Y_OFFSET)
-                        + y;
-                    let density = terrain_shape[block_index];
+                    let density = density[density_index(x + 1, y + 1, z + 1)];
 
                     if density <= 0.0 {
                         if chunk_position.y + y as i32 <= 0 {
@@ -227,15 +600,14 @@ impl Earth {
                 for y in (0..Chunk::SIZE).rev() {
                     let block_height = chunk_position.y + y as i32;
 
-                    let block_index = x * (Chunk::SIZE * (Chunk::SIZE + Y_OFFSET))
-                        + z * (Chunk::SIZE + Y_OFFSET)
-                        + y;
-                    let density = terrain_shape[block_index];
-
-                    let block = if density <= 0.0 {
+                    let block = if density[density_index(x + 1, y + 1, z + 1)] <= 0.0 {
                         if block_height == 0 {
                             layer = 1;
-                            biome.surface_liquid
+                            if climate.is_freezing(x << 4 | z, block_height) {
+                                ice
+                            } else {
+                                biome.surface_liquid
+                            }
                         } else if block_height < 0 {
                             layer = 1;
                             biome.sub_surface_liquid
@@ -249,8 +621,22 @@ impl Earth {
                     } else if block_height < 2 && base_height < 2.0 {
                         layer += 1;
                         biome.sand
+                    } else if block_height < 3 && river_distance[x << 4 | z] < RIVER_BANK_WIDTH {
+                        layer += 1;
+                        biome.sand
                     } else {
-                        let block = if layer < 1 {
+                        let block = if layer < 1
+                            && block_height < 6
+                            && river_distance[x << 4 | z] < RIVER_BANK_WIDTH
+                        {
+                            // Bare river banks
+                            biome.mid_layer_block
+                        } else if layer < 3 && is_steep(&density, x + 1, y + 1, z + 1) {
+                            // Nothing stays on cliffs, the rock is exposed.
+                            biome.bottom_layer_block
+                        } else if layer < 1 && climate.is_freezing(x << 4 | z, block_height) {
+                            snow
+                        } else if layer < 1 {
                             biome.top_layer_block
                         } else if layer < 3 {
                             biome.mid_layer_block
@@ -265,55 +651,173 @@ impl Earth {
                 }
             }
         }
+
+        return density;
+    }
+
+    // The liquid the terrain generation fills each block with. Indexed like the density, so it
+    // includes the blocks bordering the chunk.
+    fn generated_liquids(
+        &self,
+        chunk_position: IVec3,
+        column_noise: &ColumnNoise,
+        density: &[f32],
+    ) -> Vec<Option<BlockId>> {
+        let mut liquids = vec![None; density.len()];
+        if chunk_position.y - 1 > 0 {
+            return liquids;
+        }
+
+        for padded_x in 0..PADDED_SIZE {
+            for padded_z in 0..PADDED_SIZE {
+                let x = chunk_position.x + padded_x as i32 - 1;
+                let z = chunk_position.z + padded_z as i32 - 1;
+
+                let neighbour;
+                let column_noise = if (1..=Chunk::SIZE).contains(&padded_x)
+                    && (1..=Chunk::SIZE).contains(&padded_z)
+                {
+                    column_noise
+                } else {
+                    neighbour = self.column_noise(x, z);
+                    &neighbour
+                };
+                let index = column_index(x, z);
+                let biome = self.biomes.get(column_noise.climate.biomes[index]);
+
+                for padded_y in 0..PADDED_HEIGHT {
+                    let block_height = chunk_position.y + padded_y as i32 - 1;
+                    if block_height > 0 {
+                        break;
+                    }
+
+                    let density_index = density_index(padded_x, padded_y, padded_z);
+                    if density[density_index] > 0.0 {
+                        continue;
+                    }
+
+                    // Same as in 'generate_terrain', the surface freezes to ice.
+                    liquids[density_index] = if block_height < 0 {
+                        Some(biome.sub_surface_liquid)
+                    } else if column_noise.climate.is_freezing(index, block_height) {
+                        None
+                    } else {
+                        Some(biome.surface_liquid)
+                    };
+                }
+            }
+        }
+
+        return liquids;
     }
 
-    fn carve_caves(&self, chunk_position: IVec3, chunk: &mut Chunk) {
+    fn carve_caves(
+        &self,
+        chunk_position: IVec3,
+        chunk: &mut Chunk,
+        column_noise: &ColumnNoise,
+        liquids: &[Option<BlockId>],
+    ) {
         let air = Blocks::get().get_id("air");
 
-        let biome = self.biomes.get_biome();
+        // The caves are generated for the chunk and the columns bordering it, so it is known if
+        // the liquid at the edges of the chunk has carved out space beside it.
         let (caves, _, _) = self.caves.generate_3d(
-            chunk_position.x as f32,
+            (chunk_position.x - 1) as f32,
             chunk_position.y as f32,
-            chunk_position.z as f32,
-            Chunk::SIZE,
+            (chunk_position.z - 1) as f32,
+            PADDED_SIZE,
             Chunk::SIZE,
+            PADDED_SIZE,
+        );
+        let (tunnels, _, _) = self.tunnels.generate_3d(
+            (chunk_position.x - 1) as f32,
+            chunk_position.y as f32,
+            (chunk_position.z - 1) as f32,
+            PADDED_SIZE,
             Chunk::SIZE,
+            PADDED_SIZE,
         );
-        caves
-            .into_iter()
-            .zip(chunk.blocks.iter_mut())
-            .enumerate()
-            .for_each(|(i, (mut density, block))| {
-                // TODO: Caves and water do not cooperate well. You carve the surface without
-                // knowing there's water there and you get reverse moon pools underwater. Instead
-                // we just push the caves underground, causing there to be no cave entraces at the
-                // surface. There either needs to be a way to exclude caves from being generated
-                // beneath water, or some way to intelligently fill carved out space that touches
-                // water.
-                const DECAY_POINT: i32 = -32;
-                let y = chunk_position.y + (i & 0b1111) as i32;
-                let density_offset = (y - DECAY_POINT).max(0) as f32 * 1.0 / 64.0;
-                density += density_offset;
-
-                if (density / 2.0) < 0.001
-                    && *block != biome.surface_liquid
-                    && *block != biome.sub_surface_liquid
+
+        // The blocks that are carved out, for the chunk and the columns bordering it.
+        let mut carved = vec![false; caves.len()];
+
+        for padded_x in 0..PADDED_SIZE {
+            for padded_z in 0..PADDED_SIZE {
+                let x = chunk_position.x + padded_x as i32 - 1;
+                let z = chunk_position.z + padded_z as i32 - 1;
+
+                let neighbour;
+                let column_noise = if (1..=Chunk::SIZE).contains(&padded_x)
+                    && (1..=Chunk::SIZE).contains(&padded_z)
                 {
-                    *block = air;
+                    column_noise
+                } else {
+                    neighbour = self.column_noise(x, z);
+                    &neighbour
+                };
+                let wetness = column_noise.wetness(column_index(x, z));
+
+                for y in 0..Chunk::SIZE {
+                    // Liquid is never carved
+                    if liquids[density_index(padded_x, y + 1, padded_z)].is_some() {
+                        continue;
+                    }
+
+                    // Caves beneath water are pushed underground so they don't breach the sea
+                    // floor or river beds. On dry land they are free to reach the surface and
+                    // make entrances.
+                    let block_height = chunk_position.y + y as i32;
+                    let offset = wetness * (block_height - CAVE_DECAY_POINT).max(0) as f32 / 64.0;
+
+                    let index = cave_index(padded_x, y, padded_z);
+                    let is_cave = (caves[index] + offset) / 2.0 < 0.001;
+                    let is_tunnel = tunnels[index] + offset < TUNNEL_RADIUS;
+
+                    // Carved out space beneath liquid would leave the liquid hanging in the air.
+                    // Those blocks are left as they are to seal it off, so the sea doesn't drain
+                    // into the caves beneath it.
+                    carved[index] = (is_cave || is_tunnel)
+                        && liquids[density_index(padded_x, y + 2, padded_z)].is_none();
                 }
-            });
-    }
+            }
+        }
 
-    fn generate_features(&self, chunk_position: IVec3, chunk: &mut Chunk) {
-        // TODO: It should be unique to each chunk but I don't know how.
-        let seed = self
-            .seed
-            .overflowing_add(chunk_position.x.pow(2))
-            .0
-            .overflowing_add(chunk_position.z)
-            .0;
-        let mut rng = rand::rngs::StdRng::seed_from_u64(seed as u64);
+        let climate = &column_noise.climate;
+        for x in 0..Chunk::SIZE {
+            for z in 0..Chunk::SIZE {
+                for y in 0..Chunk::SIZE {
+                    if carved[cave_index(x + 1, y, z + 1)] {
+                        chunk[[x, y, z]] = air;
+                        continue;
+                    }
 
+                    // Liquid beside carved out space, be it a cave, a tunnel or the face of a
+                    // cliff it cuts through, is marked so it starts flowing into it once the chunk
+                    // is loaded.
+                    if liquids[density_index(x + 1, y + 1, z + 1)].is_none() {
+                        continue;
+                    }
+                    let beside_carved = [(x + 2, z + 1), (x, z + 1), (x + 1, z + 2), (x + 1, z)]
+                        .into_iter()
+                        .any(|(x, z)| carved[cave_index(x, y, z)]);
+                    if beside_carved {
+                        let biome = self.biomes.get(climate.biomes[x << 4 | z]);
+                        chunk[[x, y, z]] = biome.spreading_liquid;
+                    }
+                }
+            }
+        }
+    }
+
+    fn generate_features(
+        &self,
+        chunk_position: IVec3,
+        chunk: &mut Chunk,
+        climate: &Climate,
+        density: &[f32],
+        liquids: &[Option<BlockId>],
+    ) {
         let air = Blocks::get().get_id("air");
 
         // TODO: This should be done at terrain generation, but it clutters the code and it's in
@@ -339,10 +843,114 @@ impl Earth {
             }
         }
 
-        let biome = self.biomes.get_biome();
+        for (biome_index, biome) in self.biomes.iter().enumerate() {
+            if !climate.biomes.contains(&biome_index) {
+                continue;
+            }
 
-        for blueprint in biome.blueprints.iter() {
-            let terrain_feature = blueprint.construct(chunk_position, &surface, &mut rng);
+            // Each biome's blueprints may only use the part of the surface that is inside the
+            // biome.
+            let biome_surface: Vec<_> = surface
+                .iter()
+                .zip(climate.biomes.iter())
+                .map(|(surface, column_biome)| {
+                    if *column_biome == biome_index {
+                        *surface
+                    } else {
+                        None
+                    }
+                })
+                .collect();
+
+            for (blueprint_index, blueprint) in biome.blueprints.iter().enumerate() {
+                // Every blueprint gets its own rng so that the placement of one doesn't depend on
+                // how many random numbers the ones before it used.
+                let salt = (biome_index as u32) << 16 | blueprint_index as u32;
+                let mut rng = rand::rngs::StdRng::seed_from_u64(hash_position(
+                    self.seed,
+                    chunk_position,
+                    salt,
+                ));
+
+                let terrain_feature = blueprint.construct(chunk_position, &biome_surface, &mut rng);
+
+                if terrain_feature.blocks.is_empty() {
+                    continue;
+                }
+
+                terrain_feature.apply(chunk, chunk_position);
+
+                chunk.terrain_features.push(terrain_feature);
+            }
+        }
+
+        // Decorations go on top of what the blueprints leave, so they are placed after. Of the
+        // neighbouring chunks only the liquid is known, it is what they are placed next to.
+        let liquid = |position: IVec3| {
+            let position = position + IVec3::ONE;
+            liquids[density_index(
+                position.x as usize,
+                position.y as usize,
+                position.z as usize,
+            )]
+        };
+        // Above the chunk they can only take the place of what the terrain generation leaves
+        // where it isn't solid, air or liquid. Ice at the surface is solid too.
+        let above_chunk = |position: IVec3| {
+            let (x, y, z) = (
+                position.x as usize,
+                position.y as usize,
+                position.z as usize,
+            );
+            if y + 1 >= PADDED_HEIGHT {
+                return None;
+            }
+
+            let index = density_index(x + 1, y + 1, z + 1);
+            if density[index] > 0.0 {
+                return None;
+            } else if liquids[index].is_some() {
+                return liquids[index];
+            }
+
+            let block_height = chunk_position.y + y as i32;
+            if block_height == 0 && climate.is_freezing(x << 4 | z, block_height) {
+                return None;
+            }
+
+            Some(self.biomes.get(climate.biomes[x << 4 | z]).air)
+        };
+        for (biome_index, biome) in self.biomes.iter().enumerate() {
+            if !climate.biomes.contains(&biome_index) {
+                continue;
+            }
+
+            for (decoration_index, decoration) in biome.decorations.iter().enumerate() {
+                // Salted past the biome indices used by the blueprints, below the ores
+                let salt = 0xFFFD << 16 | (biome_index as u32) << 8 | decoration_index as u32;
+                let mut rng = rand::rngs::StdRng::seed_from_u64(hash_position(
+                    self.seed,
+                    chunk_position,
+                    salt,
+                ));
+                decoration.generate(
+                    chunk_position,
+                    chunk,
+                    &climate.biomes,
+                    biome_index,
+                    &liquid,
+                    &above_chunk,
+                    &mut rng,
+                );
+            }
+        }
+
+        for (ore_index, ore) in self.ores.iter().enumerate() {
+            // Salted past the biome indices used by the blueprints
+            let salt = 0xFFFF << 16 | ore_index as u32;
+            let mut rng =
+                rand::rngs::StdRng::seed_from_u64(hash_position(self.seed, chunk_position, salt));
+            let terrain_feature = ore.construct(chunk_position, &climate.biomes, &mut rng);
 
             if terrain_feature.blocks.is_empty() {
                 continue;
@@ -353,6 +961,71 @@ impl Earth {
             chunk.terrain_features.push(terrain_feature);
         }
     }
+
+    fn place_structure_features(
+        &self,
+        structure: &Structure,
+        origin: IVec3,
+        chunk_position: IVec3,
+        chunk: &mut Chunk,
+    ) {
+        for terrain_feature in structure.construct(origin) {
+            terrain_feature.apply(chunk, chunk_position);
+            chunk.terrain_features.push(terrain_feature);
+        }
+    }
+}
+
+// Index into the density generated for a chunk, see 'Earth::generate_terrain'. The coordinates are
+// offset by one from the chunk's, as it includes the blocks bordering it.
+fn density_index(x: usize, y: usize, z: usize) -> usize {
+    x * (PADDED_SIZE * PADDED_HEIGHT) + z * PADDED_HEIGHT + y
+}
+
+// Index into the cave noise generated for a chunk, see 'Earth::carve_caves'. The x and z
+// coordinates are offset by one like the density's, y is not.
+fn cave_index(x: usize, y: usize, z: usize) -> usize {
+    x * (PADDED_SIZE * Chunk::SIZE) + z * Chunk::SIZE + y
+}
+
+// A solid block is on a steep slope if one of its horizontal neighbours is open both at its
+// height and the one below, single steps in the terrain don't count. Takes padded coordinates, so
+// all the neighbours are in the density.
+fn is_steep(density: &[f32], x: usize, y: usize, z: usize) -> bool {
+    [(x + 1, z), (x - 1, z), (x, z + 1), (x, z - 1)]
+        .into_iter()
+        .any(|(x, z)| {
+            density[density_index(x, y, z)] <= 0.0 && density[density_index(x, y - 1, z)] <= 0.0
+        })
+}
+
+// Index of the block column at x, z in its chunk column, x << 4 | z.
+fn column_index(x: i32, z: i32) -> usize {
+    let size = Chunk::SIZE as i32;
+    (x.rem_euclid(size) << 4 | z.rem_euclid(size)) as usize
+}
+
+/// Hash a position into a seed for random number generation, so that anything randomly generated
+/// at the position is the same every time. 'salt' separates the uses at the same position.
+fn hash_position(seed: i32, position: IVec3, salt: u32) -> u64 {
+    // splitmix64 finalizer, each step mixes one value into the hash.
+    fn mix(mut x: u64) -> u64 {
+        x = x.wrapping_add(0x9E3779B97F4A7C15);
+        x = (x ^ (x >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        x = (x ^ (x >> 27)).wrapping_mul(0x94D049BB133111EB);
+        x ^ (x >> 31)
+    }
+
+    let mut hash = mix(seed as u32 as u64);
+    for value in [
+        position.x as u32,
+        position.y as u32,
+        position.z as u32,
+        salt,
+    ] {
+        hash = mix(hash ^ value as u64);
+    }
+    return hash;
 }
 
 impl TerrainGenerator for Earth {
@@ -365,11 +1038,20 @@ impl TerrainGenerator for Earth {
         let mut chunk = Chunk::default();
 
         let air = Blocks::get().get_id("air");
+        let structure = self.find_structure(chunk_position);
+
         if MAX_HEIGHT < chunk_position.y {
-            // Don't waste time generating if it is guaranteed to be air.
-            chunk.make_uniform(air);
+            if let Some((structure, origin)) = structure {
+                // Tall structures can reach above the terrain
+                chunk.blocks = vec![air; Chunk::SIZE.pow(3)];
+                self.place_structure_features(structure, origin, chunk_position, &mut chunk);
+            } else {
+                // Don't waste time generating if it is guaranteed to be air.
+                chunk.make_uniform(air);
+            }
         } else {
-            self.generate_terrain(chunk_position, &mut chunk);
+            let column_noise = self.column_noise(chunk_position.x, chunk_position.z);
+            let density = self.generate_terrain(chunk_position, &mut chunk, &column_noise);
 
             // TODO: Might make sense to test against water too.
             //
@@ -384,15 +1066,245 @@ impl TerrainGenerator for Earth {
                 }
             }
 
-            if uniform {
+            if uniform && structure.is_none() {
                 chunk.make_uniform(air);
                 return chunk;
             }
 
-            self.carve_caves(chunk_position, &mut chunk);
-            self.generate_features(chunk_position, &mut chunk);
+            let liquids = self.generated_liquids(chunk_position, &column_noise, &density);
+            self.carve_caves(chunk_position, &mut chunk, &column_noise, &liquids);
+            self.generate_features(
+                chunk_position,
+                &mut chunk,
+                &column_noise.climate,
+                &density,
+                &liquids,
+            );
+
+            if let Some((structure, origin)) = structure {
+                self.place_structure_features(structure, origin, chunk_position, &mut chunk);
+            }
         }
 
         return chunk;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, fmt::Write};
+
+    use super::*;
+
+    const SNAPSHOT_PATH: &str = "src/world/snapshots/generate_chunk.txt";
+
+    fn blocks() -> &'static Blocks {
+        crate::assets::load_blocks()
+    }
+
+    // FNV-1a, the standard library's hasher isn't guaranteed to be the same between versions.
+    fn chunk_hash(chunk: &Chunk) -> u64 {
+        let blocks = blocks();
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for block_id in chunk.blocks.iter() {
+            // Block ids are assigned when the world is created, the names are what stay the same.
+            for byte in blocks.get_config(block_id).name.bytes().chain([0]) {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(0x100000001b3);
+            }
+        }
+        hash
+    }
+
+    fn snapshot_positions() -> Vec<IVec3> {
+        vec![
+            IVec3::new(0, 0, 0),
+            IVec3::new(0, -16, 0),
+            IVec3::new(0, 16, 0),
+            IVec3::new(-32, 0, 48),
+            IVec3::new(512, 0, -512),
+            IVec3::new(4096, -64, 4096),
+            IVec3::new(0, MAX_HEIGHT + 16, 0),
+        ]
+    }
+
+    #[test]
+    fn position_hash_is_stable() {
+        assert_eq!(hash_position(0, IVec3::ZERO, 0), 0x78ae5a9a6b5fd45e);
+        assert_eq!(
+            hash_position(0, IVec3::new(16, -32, 48), 0),
+            0x496499fc0c2cb77a
+        );
+        assert_eq!(hash_position(1, IVec3::ZERO, 0), 0x348c3d1af8bec8e5);
+        assert_eq!(hash_position(0, IVec3::ZERO, 1), 0xa5d3758b95b92366);
+        assert_eq!(
+            hash_position(-5, IVec3::new(-16, 0, 16), 1 << 16 | 2),
+            0x789f389baa3012ea
+        );
+    }
+
+    #[test]
+    fn position_hash_does_not_collide() {
+        let mut hashes = HashSet::new();
+        for x in -16..16 {
+            for y in -4..4 {
+                for z in -16..16 {
+                    let position = IVec3::new(x, y, z) * Chunk::SIZE as i32;
+                    assert!(hashes.insert(hash_position(0, position, 0)));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn generate_chunk_is_deterministic() {
+        let first = Earth::new(0, blocks());
+        let second = Earth::new(0, blocks());
+
+        for position in snapshot_positions() {
+            let first_chunk = first.generate_chunk(position);
+            let second_chunk = second.generate_chunk(position);
+            assert_eq!(
+                first_chunk.blocks, second_chunk.blocks,
+                "chunk at {} differs between two generators with the same seed",
+                position
+            );
+            // Generating the same chunk again must not be affected by what was generated before.
+            assert_eq!(first.generate_chunk(position).blocks, first_chunk.blocks);
+        }
+    }
+
+    // An earth that computes the 2d noise again for every chunk.
+    fn uncached_earth(seed: i32) -> Earth {
+        Earth {
+            column_noise: Arc::new(ColumnCache::new(0)),
+            ..Earth::new(seed, blocks())
+        }
+    }
+
+    #[test]
+    fn island_is_surrounded_by_ocean() {
+        let radius = 256;
+        let earth = Earth::island(0, blocks(), radius);
+        for step in 0..64 {
+            let angle = step as f32 / 64.0 * std::f32::consts::TAU;
+            for distance in [
+                radius as f32 + 2.0,
+                radius as f32 * 1.5,
+                radius as f32 * 4.0,
+            ] {
+                let x = (angle.cos() * distance) as i32;
+                let z = (angle.sin() * distance) as i32;
+                assert_eq!(
+                    earth.surface_height(x, z),
+                    None,
+                    "land at {} {}, past the radius of the island",
+                    x,
+                    z
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn column_cache_does_not_change_chunks() {
+        let cached = Earth::new(0, blocks());
+        let uncached = uncached_earth(0);
+
+        for position in snapshot_positions() {
+            assert_eq!(
+                cached.generate_chunk(position).blocks,
+                uncached.generate_chunk(position).blocks,
+                "chunk at {} differs when the column noise is cached",
+                position
+            );
+        }
+    }
+
+    // Generates a few stacks of chunks with and without the column cache. Run with:
+    // cargo test --release benchmark_column_cache -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn benchmark_column_cache() {
+        let mut positions = Vec::new();
+        for x in 0..4 {
+            for z in 0..4 {
+                for y in -4..=MAX_HEIGHT / Chunk::SIZE as i32 {
+                    positions.push(IVec3::new(x, y, z) * Chunk::SIZE as i32);
+                }
+            }
+        }
+
+        let measure = |earth: &Earth| {
+            let start = std::time::Instant::now();
+            for position in positions.iter() {
+                std::hint::black_box(earth.generate_chunk(*position));
+            }
+            start.elapsed()
+        };
+
+        let uncached = measure(&uncached_earth(0));
+        let cached = measure(&Earth::new(0, blocks()));
+        println!(
+            "{} chunks, uncached: {:?} ({:?}/chunk), cached: {:?} ({:?}/chunk), {:.2}x",
+            positions.len(),
+            uncached,
+            uncached / positions.len() as u32,
+            cached,
+            cached / positions.len() as u32,
+            uncached.as_secs_f64() / cached.as_secs_f64()
+        );
+    }
+
+    // Compares against the recorded snapshot, if there is one. Changes to the terrain generation
+    // that are intended need the snapshot to be re-recorded by running the test with
+    // UPDATE_SNAPSHOTS=1, and committed along with the change.
+    #[test]
+    fn generate_chunk_matches_snapshot() {
+        let mut snapshot = String::new();
+        for seed in [0, 1234] {
+            let earth = Earth::new(seed, blocks());
+            for position in snapshot_positions() {
+                let chunk = earth.generate_chunk(position);
+                writeln!(
+                    snapshot,
+                    "{} {} {} {} {:016x}",
+                    seed,
+                    position.x,
+                    position.y,
+                    position.z,
+                    chunk_hash(&chunk)
+                )
+                .unwrap();
+            }
+        }
+
+        if std::env::var("UPDATE_SNAPSHOTS").is_ok_and(|value| value == "1") {
+            std::fs::create_dir_all("src/world/snapshots").unwrap();
+            std::fs::write(SNAPSHOT_PATH, snapshot).unwrap();
+            return;
+        }
+
+        // Nothing to compare against until a snapshot has been recorded.
+        let recorded = match std::fs::read_to_string(SNAPSHOT_PATH) {
+            Ok(recorded) => recorded,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                eprintln!(
+                    "Skipped, there is no snapshot at {}, record it by running the test with \
+                    UPDATE_SNAPSHOTS=1",
+                    SNAPSHOT_PATH
+                );
+                return;
+            }
+            Err(e) => panic!(
+                "Could not read the snapshot at {}\nError: {}",
+                SNAPSHOT_PATH, e
+            ),
+        };
+        assert_eq!(
+            recorded, snapshot,
+            "generated chunks differ from the snapshot at {}",
+            SNAPSHOT_PATH
+        );
+    }
+}
@@ -0,0 +1,208 @@
+use fmc::{
+    blocks::{BlockId, Blocks, BLOCK_CONFIG_PATH},
+    prelude::*,
+    world::{chunk::Chunk, TerrainGenerator},
+};
+use serde::{Deserialize, Serialize};
+
+/// The kind of terrain the world is generated with. It is chosen when the world is created and
+/// stored with it.
+#[derive(Default, Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WorldType {
+    /// Endless continents and oceans
+    #[default]
+    Normal,
+    /// Flat layers of blocks
+    Flat { layers: Vec<FlatLayer> },
+    /// Nothing but a platform to spawn on
+    Void,
+    /// A single landmass surrounded by ocean
+    Island { radius: u32 },
+}
+
+impl std::fmt::Display for WorldType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Normal => write!(f, "normal"),
+            Self::Flat { layers } => {
+                write!(f, "flat (")?;
+                for (i, layer) in layers.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}:{}", layer.block, layer.thickness)?;
+                }
+                write!(f, ")")
+            }
+            Self::Void => write!(f, "void"),
+            Self::Island { radius } => write!(f, "island (radius {})", radius),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+pub struct FlatLayer {
+    pub block: String,
+    pub thickness: u32,
+}
+
+impl FlatLayer {
+    /// Parse a list of layers of the format "stone:32, dirt:3, grass", bottom layer first. The
+    /// thickness is 1 if left out.
+    pub fn parse_list(list: &str) -> Result<Vec<FlatLayer>, String> {
+        list.split(',')
+            .map(|layer| {
+                let layer = layer.trim();
+                let (block, thickness) = match layer.split_once(':') {
+                    Some((block, thickness)) => match thickness.trim().parse::<u32>() {
+                        Ok(thickness) => (block.trim(), thickness),
+                        Err(_) => {
+                            return Err(format!(
+                                "The thickness of the layer '{}' must be a positive number",
+                                layer
+                            ))
+                        }
+                    },
+                    None => (layer, 1),
+                };
+
+                if block.is_empty() {
+                    return Err("A layer can't be empty".to_owned());
+                }
+
+                Ok(FlatLayer {
+                    block: block.to_owned(),
+                    thickness,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Terrain of flat layers. The top layer is at height 0.
+#[derive(Clone)]
+pub struct Flat {
+    // Block at each height, starting from the bottom layer.
+    blocks: Vec<BlockId>,
+    bottom: i32,
+    air: BlockId,
+}
+
+impl Flat {
+    pub fn new(layers: &[FlatLayer], blocks: &Blocks) -> Self {
+        let mut errors = Vec::new();
+        let mut layer_blocks = Vec::new();
+
+        for layer in layers {
+            if !blocks.contains_block(&layer.block) {
+                errors.push(format!(
+                    "The flat world has a layer of '{}', but no block by that name exists. Make \
+                    sure a block by the same name is present at '{}'",
+                    layer.block, BLOCK_CONFIG_PATH
+                ));
+                continue;
+            }
+
+            let block_id = blocks.get_id(&layer.block);
+            layer_blocks.extend(std::iter::repeat(block_id).take(layer.thickness as usize));
+        }
+
+        if !errors.is_empty() {
+            panic!(
+                "Startup failed while setting up the flat world.\n{}",
+                errors.join("\n")
+            );
+        }
+
+        Self {
+            bottom: 1 - layer_blocks.len() as i32,
+            blocks: layer_blocks,
+            air: blocks.get_id("air"),
+        }
+    }
+
+    fn block_at(&self, height: i32) -> BlockId {
+        usize::try_from(height - self.bottom)
+            .ok()
+            .and_then(|index| self.blocks.get(index))
+            .copied()
+            .unwrap_or(self.air)
+    }
+}
+
+impl TerrainGenerator for Flat {
+    fn generate_chunk(&self, chunk_position: IVec3) -> Chunk {
+        let mut chunk = Chunk::default();
+
+        // All columns are the same
+        let column: Vec<BlockId> = (0..Chunk::SIZE as i32)
+            .map(|y| self.block_at(chunk_position.y + y))
+            .collect();
+
+        if column.iter().all(|block_id| *block_id == column[0]) {
+            chunk.make_uniform(column[0]);
+            return chunk;
+        }
+
+        chunk.blocks = Vec::with_capacity(Chunk::SIZE.pow(3));
+        for _ in 0..Chunk::SIZE.pow(2) {
+            chunk.blocks.extend_from_slice(&column);
+        }
+
+        return chunk;
+    }
+}
+
+/// Empty space with a small platform at the center of the world to spawn on.
+#[derive(Clone)]
+pub struct Void {
+    platform: BlockId,
+    air: BlockId,
+}
+
+impl Void {
+    // The platform extends this many blocks from the center
+    const PLATFORM_RADIUS: i32 = 2;
+
+    pub fn new(blocks: &Blocks) -> Self {
+        Self {
+            platform: blocks.get_id("stone"),
+            air: blocks.get_id("air"),
+        }
+    }
+}
+
+impl TerrainGenerator for Void {
+    fn generate_chunk(&self, chunk_position: IVec3) -> Chunk {
+        let mut chunk = Chunk::default();
+
+        // The platform is at height 0, with its center at the origin.
+        let platform_min = IVec3::new(-Self::PLATFORM_RADIUS, 0, -Self::PLATFORM_RADIUS);
+        let platform_max = IVec3::new(Self::PLATFORM_RADIUS, 0, Self::PLATFORM_RADIUS);
+        let chunk_max = chunk_position + IVec3::splat(Chunk::SIZE as i32 - 1);
+        if platform_min.cmpgt(chunk_max).any() || platform_max.cmplt(chunk_position).any() {
+            chunk.make_uniform(self.air);
+            return chunk;
+        }
+
+        chunk.blocks = vec![self.air; Chunk::SIZE.pow(3)];
+        for x in platform_min.x..=platform_max.x {
+            for z in platform_min.z..=platform_max.z {
+                let position = IVec3::new(x, 0, z) - chunk_position;
+                if position.cmplt(IVec3::ZERO).any()
+                    || position.cmpge(IVec3::splat(Chunk::SIZE as i32)).any()
+                {
+                    continue;
+                }
+                chunk[[
+                    position.x as usize,
+                    position.y as usize,
+                    position.z as usize,
+                ]] = self.platform;
+            }
+        }
+
+        return chunk;
+    }
+}